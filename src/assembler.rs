@@ -1,11 +1,63 @@
+use std::{collections::HashSet, path::PathBuf};
+
 use crate::{
-    assembler_context::{AssemblerContext, AssemblerError},
-    parsing::parse_str,
+    assembler_context::{AssemblerContext, AssemblerError, AssemblerErrorKind},
+    diagnostic::Diagnostic,
+    instructions::AValue,
+    location::{LineIndex, Location},
+    object::{ObjectFile, ObjectWord},
+    optimizer::{OptimizationReport, Optimizer},
+    options::AssemblerOptions,
+    parsing::{parse_str, preprocess, ParsedInstruction},
+    symbol_table::{Symbol, SymbolKind},
 };
 
+/// An assembled program together with the information needed to debug it.
+#[derive(Debug)]
+pub struct AssembledProgram {
+    /// The machine code, one word per instruction.
+    pub words: Vec<u16>,
+    /// The source every word was assembled from, so `sources[i]` belongs to `words[i]`.
+    pub sources: Vec<Location>,
+    /// Every label and variable that was defined by the program.
+    pub symbols: Vec<Symbol>,
+    /// The words every rule saved, if the program was optimized.
+    pub optimization: Option<OptimizationReport>,
+}
+
+impl From<Vec<u16>> for AssembledProgram {
+    /// A program that only consists of its words, such as a linked or loaded one.
+    fn from(words: Vec<u16>) -> Self {
+        Self {
+            words,
+            sources: Vec::new(),
+            symbols: Vec::new(),
+            optimization: None,
+        }
+    }
+}
+
+/// Everything a run over the program produced.
+struct Output {
+    program: AssembledProgram,
+    errors: Vec<AssemblerError>,
+    warnings: Vec<Diagnostic>,
+    /// The address and text of every a-instruction that was left for the linker.
+    references: Vec<(usize, String)>,
+    exports: Vec<String>,
+    /// The symbols left for the linker that are variables rather than imported labels.
+    variables: Vec<String>,
+}
+
 pub struct Assembler<'a> {
     context: AssemblerContext,
     input: &'a str,
+    file_name: Option<String>,
+    include_dirs: Vec<PathBuf>,
+    relocatable: bool,
+    pseudo_instructions: bool,
+    optimizer: Option<Optimizer>,
+    options: AssemblerOptions,
 }
 
 impl<'a> Assembler<'a> {
@@ -13,20 +65,388 @@ impl<'a> Assembler<'a> {
         Self {
             context: AssemblerContext::default(),
             input,
+            file_name: None,
+            include_dirs: Vec::new(),
+            relocatable: false,
+            pseudo_instructions: true,
+            optimizer: None,
+            options: AssemblerOptions::default(),
+        }
+    }
+
+    /// Sets the file name that is reported alongside the line and column of errors.
+    /// Files included by the program are looked up relative to it.
+    pub fn with_file_name(mut self, file_name: &str) -> Self {
+        self.file_name = Some(file_name.to_string());
+        self
+    }
+
+    /// Adds a directory that is searched for included files
+    /// which are not found next to the including file.
+    pub fn with_include_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.include_dirs.push(dir.into());
+        self
+    }
+
+    /// Rejects pseudo-instructions such as `goto LOOP` or `push D`,
+    /// so only the instructions of the Hack machine language are accepted.
+    pub fn without_pseudo_instructions(mut self) -> Self {
+        self.pseudo_instructions = false;
+        self
+    }
+
+    /// Sets the options the program is assembled with, such as its dialect,
+    /// the memory layout of the machine and the symbols that are defined outside of it.
+    pub fn with_options(mut self, options: AssemblerOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Runs the optimizer over the parsed instructions before they are assembled.
+    pub fn with_optimizer(mut self, optimizer: Optimizer) -> Self {
+        self.optimizer = Some(optimizer);
+        self
+    }
+
+    /// Assembles the program and returns the first error that was found.
+    pub fn assemble(self) -> Result<Vec<u16>, AssemblerError> {
+        self.assemble_program().map(|program| program.words)
+    }
+
+    /// Like [`Assembler::assemble`], but keeps the sources and symbols of the program.
+    pub fn assemble_program(self) -> Result<AssembledProgram, AssemblerError> {
+        let output = self.run();
+
+        match output.errors.into_iter().next() {
+            Some(error) => Err(error),
+            None => Ok(output.program),
         }
     }
 
-    pub fn assemble(mut self) -> Result<Vec<u16>, AssemblerError> {
-        let parser_output = parse_str(self.input)?;
+    /// Assembles the program into a module that is linked with others by [`crate::link`].
+    /// Every a-instruction that refers to a label or to a symbol the module does not define
+    /// is left for the linker. Such a symbol is a label of another module if it is listed
+    /// in an `.import`, otherwise it is a variable.
+    pub fn assemble_object(mut self) -> Result<ObjectFile, AssemblerError> {
+        self.relocatable = true;
+        let output = self.run();
+
+        if let Some(error) = output.errors.into_iter().next() {
+            return Err(error);
+        }
+
+        let mut code: Vec<_> = output
+            .program
+            .words
+            .into_iter()
+            .map(ObjectWord::Word)
+            .collect();
+        for (address, reference) in output.references {
+            code[address] = ObjectWord::Reference(reference);
+        }
+
+        let symbols = output
+            .program
+            .symbols
+            .into_iter()
+            .filter(|s| s.kind != SymbolKind::Variable)
+            .collect();
+
+        Ok(ObjectFile {
+            code,
+            symbols,
+            exports: output.exports,
+            variables: output.variables,
+        })
+    }
+
+    /// Assembles the program without stopping at the first error.
+    /// Returns the output together with all warnings, or every error and warning
+    /// ordered by their file and their position in it.
+    pub fn assemble_with_diagnostics(
+        self,
+    ) -> Result<(AssembledProgram, Vec<Diagnostic>), Vec<Diagnostic>> {
+        let Output {
+            program,
+            errors,
+            warnings,
+            ..
+        } = self.run();
+        let failed = !errors.is_empty();
+
+        let mut diagnostics: Vec<Diagnostic> = errors.into_iter().map(Diagnostic::from).collect();
+        diagnostics.extend(warnings);
+        // errors of included files are kept together with the rest of their file
+        diagnostics.sort_by_cached_key(|d| {
+            d.location
+                .as_ref()
+                .map(|l| (l.file.clone(), l.line, l.column))
+        });
+
+        if failed {
+            Err(diagnostics)
+        } else {
+            Ok((program, diagnostics))
+        }
+    }
+
+    fn locate(&self, error: AssemblerError) -> AssemblerError {
+        match &self.file_name {
+            Some(file_name) => error.with_file(file_name),
+            None => error,
+        }
+    }
+
+    /// Runs every pass over the whole program, collecting errors instead of returning early.
+    fn run(mut self) -> Output {
+        // includes and macros are expanded first, so everything after works on the expanded text
+        let preprocessed = preprocess(self.input, self.file_name.as_deref(), &self.include_dirs);
+        let source_map = preprocessed.source_map;
+        let input = preprocessed.text.as_str();
+
+        let mut errors = Vec::new();
+        match AssemblerContext::with_options(&self.options) {
+            Ok(context) => self.context = context,
+            Err(e) => errors.push(e),
+        }
+
+        let mut parser_output = parse_str(input, self.options.dialect);
+        let lines = LineIndex::new(input);
+        let optimization = self.optimizer.as_ref().map(|optimizer| {
+            // the names of the labels and constants, which are only registered afterwards
+            let defined: HashSet<String> = parser_output
+                .labels
+                .iter()
+                .map(|(label, _)| label.name.to_string())
+                .chain(parser_output.constants.iter().map(|c| c.name.to_string()))
+                .collect();
+            let is_defined = |name: &str| defined.contains(name) || self.context.is_defined(name);
+
+            optimizer.optimize(
+                &mut parser_output.instructions,
+                &mut parser_output.labels,
+                &is_defined,
+            )
+        });
+        errors.append(&mut parser_output.errors);
+        // every defined label and constant, to warn about the unused ones
+        let mut definitions = Vec::new();
+        // byte offset of the instruction every word was assembled from
+        let mut offsets = Vec::new();
+        let mut references = Vec::new();
+        let mut exports = Vec::new();
+        let mut variables: Vec<String> = Vec::new();
+        let labels: Vec<_> = parser_output
+            .labels
+            .iter()
+            .map(|(l, _)| l.name.clone())
+            .collect();
 
         for (label, index) in parser_output.labels {
-            self.context.register_label(label, index)?;
+            let definition = (
+                "label",
+                label.name.clone(),
+                Location::from_range(input, &lines, label.span.clone()),
+            );
+
+            match self.context.register_label(label, index) {
+                Ok(()) => definitions.push(definition),
+                Err(e) => errors.push(e),
+            }
+        }
+
+        for constant in parser_output.constants {
+            let definition = (
+                "constant",
+                constant.name.into(),
+                Location::from_range(input, &lines, constant.span.clone()),
+            );
+
+            match self.context.register_constant(constant) {
+                Ok(()) => definitions.push(definition),
+                Err(e) => errors.push(e),
+            }
+        }
+
+        for export in parser_output.exports {
+            if labels.contains(&export.name) {
+                self.context.reference(&export.name);
+                exports.push(export.name.to_string());
+            } else {
+                let error = AssemblerErrorKind::ExportNotLabel(export.name.to_string());
+                errors.push(AssemblerError::new(error).with_range(&export.span));
+            }
+        }
+
+        let imports: Vec<_> = parser_output
+            .imports
+            .iter()
+            .map(|i| i.name.to_string())
+            .collect();
+        // a whole program has no other module to import from
+        if !self.relocatable {
+            for import in parser_output.imports {
+                if !self.context.is_defined(&import.name) {
+                    let error = AssemblerErrorKind::MissingImport(import.name.to_string());
+                    errors.push(AssemblerError::new(error).with_range(&import.span));
+                }
+            }
+        }
+
+        if !self.pseudo_instructions {
+            for span in parser_output.pseudo_instructions {
+                let name = match &input[span.clone()] {
+                    text if text.starts_with("@@") => "@@",
+                    text => text.split_whitespace().next().unwrap_or(text),
+                };
+                let error = AssemblerErrorKind::PseudoInstruction(name.to_string());
+                errors.push(AssemblerError::new(error).with_range(&span));
+            }
         }
 
         for instruction in parser_output.instructions {
-            self.context.feed_instruction(instruction)?;
+            let offset = instruction.span().start;
+            let address = self.context.current_address();
+
+            // in an object file, everything that moves with the module is resolved by the linker
+            let reference = match &instruction {
+                ParsedInstruction::AInstruction(i, _) if self.relocatable => {
+                    // like in a whole program, only a plain @name creates a variable
+                    if let AValue::Symbol { name, .. } = &i.value {
+                        let known = |names: &[String]| names.iter().any(|n| n == name);
+                        if !self.context.is_defined(name) && !known(&imports) && !known(&variables)
+                        {
+                            variables.push(name.to_string());
+                        }
+                    }
+
+                    let symbols = i.value.symbols();
+                    symbols
+                        .iter()
+                        .any(|s| self.context.is_relocatable(s))
+                        .then(|| (symbols, format!("@{}", i.value)))
+                }
+                _ => None,
+            };
+
+            let result = match reference {
+                Some((symbols, reference)) => {
+                    symbols.iter().for_each(|s| self.context.reference(s));
+                    let result = self.context.push_placeholder();
+                    if result.is_ok() {
+                        references.push((address, reference));
+                    }
+                    result.map_err(|e| e.with_range(instruction.span()))
+                }
+                None => self.context.feed_instruction(instruction),
+            };
+            offsets.resize(self.context.current_address(), offset);
+
+            match result {
+                Ok(()) => (),
+                // every following instruction would exceed the ROM as well
+                Err(e) if matches!(e.kind, AssemblerErrorKind::TooManyInstructions) => {
+                    errors.push(e);
+                    break;
+                }
+                Err(e) => errors.push(e),
+            }
+        }
+
+        let warnings = definitions
+            .into_iter()
+            .filter(|(_, name, _)| !self.context.is_referenced(name))
+            .map(|(kind, name, location)| {
+                let (location, notes) = source_map.locate(location);
+                let warning =
+                    Diagnostic::warning(format!("{} \"{}\" is never used", kind, name), location)
+                        .with_notes(notes);
+
+                match &self.file_name {
+                    Some(file_name) => warning.with_file(file_name),
+                    None => warning,
+                }
+            })
+            .collect();
+
+        let errors = preprocessed
+            .errors
+            .into_iter()
+            .chain(
+                errors
+                    .into_iter()
+                    .map(|e| source_map.locate_error(e.in_source(input))),
+            )
+            .map(|e| self.locate(e))
+            .collect();
+
+        let sources = offsets
+            .into_iter()
+            .map(|offset| {
+                let location = Location::from_line_index(input, &lines, offset);
+                let mut location = source_map.call_site(location);
+                if location.file.is_none() {
+                    location.file = self.file_name.clone();
+                }
+                location
+            })
+            .collect();
+
+        let program = AssembledProgram {
+            symbols: self.context.symbols(),
+            words: self.context.into_output(),
+            sources,
+            optimization,
+        };
+
+        Output {
+            program,
+            errors,
+            warnings,
+            references,
+            exports,
+            variables,
         }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diagnostics_collect_every_error() {
+        let program = "@40000\n(LOOP)\nD=Q\n(LOOP)\n@LOOP\n(END)\n";
+        let diagnostics = Assembler::new(program)
+            .assemble_with_diagnostics()
+            .unwrap_err();
+
+        let lines: Vec<_> = diagnostics
+            .iter()
+            .map(|d| (d.is_error(), d.location.as_ref().unwrap().line))
+            .collect();
+        assert_eq!(lines, [(true, 1), (true, 3), (true, 4), (false, 6)]);
+    }
+
+    #[test]
+    fn test_diagnostics_ordered_by_file() {
+        let dir = crate::test_dir::TestDir::new("diagnostics_ordered_by_file");
+        let lib = dir.write("lib.asm", "D=Q\nD=Q\n");
+        let main = dir.path().join("main.asm").display().to_string();
+
+        let diagnostics = Assembler::new("D=Q\n.include \"lib.asm\"\nD=Q\n")
+            .with_file_name(&main)
+            .assemble_with_diagnostics()
+            .unwrap_err();
 
-        Ok(self.context.into_output())
+        let locations: Vec<_> = diagnostics
+            .iter()
+            .map(|d| d.location.as_ref().unwrap())
+            .map(|l| (l.file.clone().unwrap(), l.line))
+            .collect();
+        assert_eq!(
+            locations,
+            [(lib.clone(), 1), (lib, 2), (main.clone(), 1), (main, 3)]
+        );
     }
 }
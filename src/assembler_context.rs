@@ -1,34 +1,198 @@
+use std::{collections::HashSet, fmt, ops::Range};
+
+use pest::Span;
 use thiserror::Error;
 
 use crate::{
-    hack_int::HackInt,
-    instructions::Label,
-    parsing::{ParseError, ParsedInstruction},
-    symbol_table::{SymbolTable, SymbolTableGetError, SymbolTableSetError},
+    diagnostic::Note,
+    hack_int::{HackInt, ParseHackIntError},
+    instructions::{Constant, Label},
+    location::Location,
+    options::{AssemblerOptions, OptionsError},
+    parsing::{IncludeError, MacroError, ParseError, ParsedInstruction},
+    symbol_table::{Symbol, SymbolKind, SymbolTable, SymbolTableGetError, SymbolTableSetError},
 };
 
 pub struct AssemblerContext {
     symbol_table: SymbolTable,
     current_variable_address: HackInt,
+    /// The first address that cannot hold a variable anymore.
+    variables_end: u16,
+    rom_size: usize,
     current_label_address: HackInt,
+    referenced: HashSet<String>,
     output: Vec<u16>,
 }
 
 #[derive(Error, Debug)]
-pub enum AssemblerError {
+pub enum AssemblerErrorKind {
     #[error("exceeded maximum number of variables")]
     TooManyVariables,
     #[error("exceeded maximum number of instructions")]
     TooManyInstructions,
+    #[error("division by zero")]
+    DivisionByZero,
+    #[error("expression overflows")]
+    ExpressionOverflow,
+    #[error(
+        "expression evaluates to {0}, which is not between 0 and {}",
+        HackInt::MAX
+    )]
+    ExpressionOutOfRange(i64),
+    #[error("label \"{0}\" points behind the last instruction the ROM can hold")]
+    LabelOutsideOfRom(String),
+    #[error("no label \"{0}:\" is defined before this reference")]
+    MissingBackwardLabel(String),
+    #[error("no label \"{0}:\" is defined after this reference")]
+    MissingForwardLabel(String),
+    #[error("only labels can be exported, \"{0}\" is not a label")]
+    ExportNotLabel(String),
+    #[error("imported symbol \"{0}\" is not defined by the program")]
+    MissingImport(String),
+    #[error("pseudo-instruction \"{0}\" is not allowed")]
+    PseudoInstruction(String),
+    #[error(transparent)]
+    OptionsError(#[from] OptionsError),
     #[error(transparent)]
     SymbolTableSetError(#[from] SymbolTableSetError),
     #[error(transparent)]
+    SymbolTableGetError(#[from] SymbolTableGetError),
+    #[error(transparent)]
     ParseError(#[from] ParseError),
+    #[error(transparent)]
+    MacroError(#[from] MacroError),
+    #[error(transparent)]
+    IncludeError(#[from] IncludeError),
+}
+
+/// An error together with the location in the source that caused it.
+#[derive(Debug)]
+pub struct AssemblerError {
+    pub kind: AssemblerErrorKind,
+    /// Boxed to keep the error small, it is returned by almost every function.
+    pub location: Option<Box<Location>>,
+    /// Further locations that explain the error, e.g. the macro calls it was expanded from.
+    pub notes: Vec<Note>,
+    /// The byte range of the source the error was found at, until [`AssemblerError::in_source`]
+    /// turns it into a location.
+    range: Option<Range<usize>>,
+}
+
+impl AssemblerError {
+    pub fn new(kind: impl Into<AssemblerErrorKind>) -> Self {
+        Self {
+            kind: kind.into(),
+            location: None,
+            notes: Vec::new(),
+            range: None,
+        }
+    }
+
+    /// Attaches the location of `span` to the error.
+    /// An already attached location is kept, since it is the more precise one.
+    pub(crate) fn with_span(mut self, span: &Span) -> Self {
+        if self.location.is_none() {
+            self.location = Some(Box::new(Location::from_span(span)));
+        }
+
+        self
+    }
+
+    /// Attaches a byte range of the source, which is located once the source is known.
+    /// Like with [`AssemblerError::with_span`], the first range is kept.
+    pub(crate) fn with_range(mut self, range: &Range<usize>) -> Self {
+        if self.location.is_none() && self.range.is_none() {
+            self.range = Some(range.clone());
+        }
+
+        self
+    }
+
+    /// Locates the byte range attached by [`AssemblerError::with_range`] inside of the source.
+    pub(crate) fn in_source(mut self, input: &str) -> Self {
+        let range = self.range.take();
+        match range.and_then(|range| Span::new(input, range.start, range.end)) {
+            Some(span) => self.with_span(&span),
+            None => self,
+        }
+    }
+
+    pub(crate) fn with_location(mut self, location: Location) -> Self {
+        if self.location.is_none() {
+            self.location = Some(Box::new(location));
+        }
+
+        self
+    }
+
+    pub(crate) fn with_notes(mut self, notes: Vec<Note>) -> Self {
+        self.notes.extend(notes);
+        self
+    }
+
+    /// Sets the file name of the attached location and notes, if there is no file name yet.
+    pub fn with_file(mut self, file: &str) -> Self {
+        let notes = self.notes.iter_mut().map(|note| &mut note.location);
+
+        for location in self.location.as_deref_mut().into_iter().chain(notes) {
+            location.file.get_or_insert_with(|| file.to_string());
+        }
+
+        self
+    }
+}
+
+impl fmt::Display for AssemblerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.location {
+            Some(location) => location.render(f, "error", &self.kind.to_string())?,
+            None => write!(f, "error: {}", self.kind)?,
+        }
+
+        Note::render_all(f, &self.notes)
+    }
+}
+
+impl std::error::Error for AssemblerError {}
+
+impl From<AssemblerErrorKind> for AssemblerError {
+    fn from(kind: AssemblerErrorKind) -> Self {
+        Self::new(kind)
+    }
+}
+
+impl From<SymbolTableSetError> for AssemblerError {
+    fn from(error: SymbolTableSetError) -> Self {
+        Self::new(error)
+    }
+}
+
+impl From<ParseError> for AssemblerError {
+    fn from(error: ParseError) -> Self {
+        Self::new(error)
+    }
+}
+
+impl From<OptionsError> for AssemblerError {
+    fn from(error: OptionsError) -> Self {
+        Self::new(error)
+    }
+}
+
+impl From<ParseHackIntError> for AssemblerError {
+    fn from(error: ParseHackIntError) -> Self {
+        Self::new(ParseError::from(error))
+    }
 }
 
 impl AssemblerContext {
-    fn set_symbol(&mut self, name: &str, value: HackInt) -> Result<(), SymbolTableSetError> {
-        self.symbol_table.set(name, value)?;
+    fn set_symbol(
+        &mut self,
+        name: &str,
+        value: HackInt,
+        kind: SymbolKind,
+    ) -> Result<(), SymbolTableSetError> {
+        self.symbol_table.set(name, value, kind)?;
         Ok(())
     }
 
@@ -37,15 +201,39 @@ impl AssemblerContext {
     }
 
     pub fn register_label(&mut self, label: Label, address: usize) -> Result<(), AssemblerError> {
+        self.define_label(&label.name, address)
+            .map_err(|e| e.with_range(&label.span))
+    }
+
+    /// Defines a label that was not parsed from a program, such as one of a [`crate::ProgramBuilder`].
+    pub fn define_label(&mut self, name: &str, address: usize) -> Result<(), AssemblerError> {
+        // a label behind a full ROM cannot be loaded by an a-instruction
+        if address > HackInt::MAX as usize {
+            return Err(AssemblerErrorKind::LabelOutsideOfRom(name.to_string()).into());
+        }
+
         let address = HackInt::new_unchecked(address as u16);
-        self.symbol_table.set(label.name, address)?;
+        self.set_symbol(name, address, SymbolKind::Label)?;
 
         Ok(())
     }
 
-    fn push_instruction(&mut self, bits: u16) -> Result<(), AssemblerError> {
-        if self.output.len() >= crate::constants::ROM_SIZE {
-            return Err(AssemblerError::TooManyInstructions);
+    pub fn register_constant(&mut self, constant: Constant) -> Result<(), AssemblerError> {
+        self.symbol_table
+            .set(constant.name, constant.value, SymbolKind::Constant)
+            .map_err(|e| AssemblerError::from(e).with_range(&constant.span))?;
+
+        Ok(())
+    }
+
+    /// Reserves the place of an instruction that can only be resolved while linking.
+    pub fn push_placeholder(&mut self) -> Result<(), AssemblerError> {
+        self.push_instruction(0)
+    }
+
+    pub(crate) fn push_instruction(&mut self, bits: u16) -> Result<(), AssemblerError> {
+        if self.output.len() >= self.rom_size {
+            return Err(AssemblerErrorKind::TooManyInstructions.into());
         }
 
         self.output.push(bits);
@@ -55,42 +243,143 @@ impl AssemblerContext {
     }
 
     pub fn feed_instruction(&mut self, instr: ParsedInstruction) -> Result<(), AssemblerError> {
-        match instr {
-            ParsedInstruction::AInstruction(i) => {
-                let bits = i.to_u16(self)?;
-                self.push_instruction(bits)
+        let span = instr.span().clone();
+        let result = match instr {
+            ParsedInstruction::AInstruction(i, _) => {
+                i.resolve(self).and_then(|bits| self.push_instruction(bits))
             }
-            ParsedInstruction::CInstruction(i) => self.push_instruction(i.to_u16()),
-        }
+            ParsedInstruction::CInstruction(i, _) => {
+                i.to_u16().and_then(|bits| self.push_instruction(bits))
+            }
+        };
+
+        result.map_err(|e| e.with_range(&span))
     }
 
     pub fn get_or_create_variable(&mut self, name: &str) -> Result<u16, AssemblerError> {
+        self.reference(name);
+
         if let Ok(value) = self.get_symbol(name) {
             return Ok(value.into());
         }
 
-        if self.current_variable_address >= crate::constants::MEMORY_SIZE {
-            return Err(AssemblerError::TooManyVariables);
+        if u16::from(self.current_variable_address) >= self.variables_end {
+            return Err(AssemblerErrorKind::TooManyVariables.into());
         }
 
-        self.set_symbol(name, self.current_variable_address)?;
+        self.set_symbol(name, self.current_variable_address, SymbolKind::Variable)?;
         let result = self.current_variable_address.into();
         self.current_variable_address.inc_unchecked();
 
         Ok(result)
     }
 
+    /// Looks up a label, constant or built-in symbol that has to be defined already.
+    /// Unlike [`AssemblerContext::get_or_create_variable`] an unknown symbol is an error.
+    pub fn get_defined_symbol(&mut self, name: &str) -> Result<u16, AssemblerError> {
+        self.reference(name);
+
+        Ok(self.get_symbol(name).map_err(AssemblerError::new)?.into())
+    }
+
+    /// Whether the symbol is a built in symbol or has been defined or created already.
+    pub fn is_defined(&self, name: &str) -> bool {
+        self.get_symbol(name).is_ok()
+    }
+
+    /// Marks a symbol as referenced without resolving it.
+    pub fn reference(&mut self, name: &str) {
+        self.referenced.insert(name.to_string());
+    }
+
+    /// Whether the value of a symbol depends on where the program is placed in the ROM,
+    /// or is not known at all yet. Only built in symbols and constants are not relocatable.
+    pub fn is_relocatable(&self, name: &str) -> bool {
+        !self.symbol_table.is_built_in(name)
+            && self.symbol_table.kind(name) != Some(SymbolKind::Constant)
+    }
+
+    /// Whether the symbol has been referenced by any of the instructions fed so far.
+    pub fn is_referenced(&self, name: &str) -> bool {
+        self.referenced.contains(name)
+    }
+
+    /// The address the next instruction will be placed at.
+    pub fn current_address(&self) -> usize {
+        self.output.len()
+    }
+
+    pub fn symbols(&self) -> Vec<Symbol> {
+        self.symbol_table.symbols()
+    }
+
     pub fn into_output(self) -> Vec<u16> {
         self.output
     }
 }
 
+impl AssemblerContext {
+    /// Creates a context for the machine the options describe,
+    /// which already knows the symbols they define.
+    pub fn with_options(options: &AssemblerOptions) -> Result<Self, AssemblerError> {
+        options.validate()?;
+
+        let mut context = Self {
+            current_variable_address: HackInt::new_unchecked(options.variables.start),
+            variables_end: options.variables.end,
+            rom_size: options.rom_size,
+            ..Self::default()
+        };
+
+        for (name, value) in &options.predefined {
+            match value {
+                Some(value) => context
+                    .symbol_table
+                    .define_built_in(name, HackInt::new_unchecked(*value)),
+                None => context.symbol_table.remove_built_in(name),
+            }
+        }
+
+        for (name, value) in &options.symbols {
+            context.set_symbol(name, HackInt::new_unchecked(*value), SymbolKind::Constant)?;
+        }
+
+        Ok(context)
+    }
+
+    /// Creates a context that already knows the given symbols.
+    /// New variables are placed behind the highest variable among them.
+    pub fn with_symbols(symbols: &[Symbol]) -> Result<Self, AssemblerError> {
+        let mut context = Self::default();
+
+        for symbol in symbols {
+            if symbol.kind == SymbolKind::Label {
+                context.define_label(&symbol.name, symbol.value as usize)?;
+                continue;
+            }
+
+            let value = HackInt::new_unchecked(symbol.value);
+            context.set_symbol(&symbol.name, value, symbol.kind)?;
+
+            if symbol.kind == SymbolKind::Variable && value >= context.current_variable_address {
+                context.current_variable_address = value;
+                context.current_variable_address.inc_unchecked();
+            }
+        }
+
+        Ok(context)
+    }
+}
+
 impl Default for AssemblerContext {
     fn default() -> Self {
         Self {
             symbol_table: SymbolTable::new(),
-            current_variable_address: HackInt::new_unchecked(16),
+            current_variable_address: HackInt::new_unchecked(crate::constants::VARIABLES.start),
+            variables_end: crate::constants::VARIABLES.end,
+            rom_size: crate::constants::ROM_SIZE,
             current_label_address: HackInt::new_unchecked(0),
+            referenced: HashSet::new(),
             output: Vec::new(),
         }
     }
@@ -0,0 +1,456 @@
+use crate::{
+    assembler::AssembledProgram,
+    assembler_context::{AssemblerContext, AssemblerError},
+    hack_int::HackInt,
+    instructions::{AInstruction, AValue, CInstruction},
+    options::AssemblerOptions,
+};
+
+/// Builds a program out of instructions instead of parsing it from text,
+/// for code generators that would otherwise concatenate and reparse assembly.
+///
+/// Every a-instruction that refers to a symbol is resolved once the program is built,
+/// so labels can be referred to before they are defined. Symbols that are neither labels
+/// nor predefined become variables, allocated in the order they are referred to, just
+/// like the [`crate::Assembler`] does.
+///
+/// Errors are collected while building and the first one is returned by
+/// [`ProgramBuilder::build`], so instructions can be chained:
+/// ```
+/// # use hack_asm::{CInstruction, ProgramBuilder};
+/// let mut builder = ProgramBuilder::new();
+/// let end = builder.unique_label("end");
+/// builder
+///     .at("counter")
+///     .c("M=M-1".parse().unwrap())
+///     .at(&end)
+///     .c("0;JMP".parse().unwrap())
+///     .label(&end);
+///
+/// assert_eq!(builder.build().unwrap(), [16, 0xFC88, 4, 0xEA87]);
+/// ```
+///
+/// The [`crate::hack!`] macro writes the same program as assembly.
+pub struct ProgramBuilder<'a> {
+    context: AssemblerContext,
+    /// The address and value of every a-instruction that is resolved by [`ProgramBuilder::build`].
+    references: Vec<(usize, Reference<'a>)>,
+    errors: Vec<AssemblerError>,
+    counter: usize,
+}
+
+/// What an a-instruction of a [`ProgramBuilder`] refers to, until every label is known.
+enum Reference<'a> {
+    Symbol(String),
+    Expression(AValue<'a>),
+}
+
+impl<'a> ProgramBuilder<'a> {
+    pub fn new() -> Self {
+        Self {
+            context: AssemblerContext::default(),
+            references: Vec::new(),
+            errors: Vec::new(),
+            counter: 0,
+        }
+    }
+
+    /// Creates a builder for the machine the options describe, see [`crate::Assembler::with_options`].
+    pub fn with_options(options: &AssemblerOptions) -> Result<Self, AssemblerError> {
+        Ok(Self {
+            context: AssemblerContext::with_options(options)?,
+            ..Self::new()
+        })
+    }
+
+    /// The address the next instruction will be placed at.
+    pub fn current_address(&self) -> usize {
+        self.context.current_address()
+    }
+
+    fn push(&mut self, word: u16) {
+        if let Err(e) = self.context.push_instruction(word) {
+            self.errors.push(e);
+        }
+    }
+
+    /// Emits `@value` for a number between 0 and 32767.
+    pub fn load(&mut self, value: u16) -> &mut Self {
+        match HackInt::try_new(value) {
+            Ok(value) => self.push(value.into()),
+            Err(e) => self.errors.push(e.into()),
+        }
+
+        self
+    }
+
+    /// Emits `@symbol`, where the symbol is a label, a predefined symbol or a variable.
+    pub fn at(&mut self, symbol: &str) -> &mut Self {
+        self.reference(Reference::Symbol(symbol.to_string()))
+    }
+
+    /// Emits an a-instruction, symbols and expressions such as `SCREEN+32` are resolved
+    /// like by [`ProgramBuilder::at`].
+    pub fn a(&mut self, instruction: AInstruction<'a>) -> &mut Self {
+        match instruction.value {
+            AValue::Literal(value) => self.load(value.into()),
+            AValue::Symbol { name, .. } => self.at(&name),
+            value => self.reference(Reference::Expression(value)),
+        }
+    }
+
+    fn reference(&mut self, reference: Reference<'a>) -> &mut Self {
+        let address = self.context.current_address();
+        self.references.push((address, reference));
+        // the word is filled in by build, once every label is known
+        if let Err(e) = self.context.push_placeholder() {
+            self.errors.push(e);
+        }
+
+        self
+    }
+
+    /// Emits a c-instruction, which fails if the ALU cannot perform its computation.
+    pub fn c(&mut self, instruction: CInstruction) -> &mut Self {
+        match instruction.to_u16() {
+            Ok(word) => self.push(word),
+            Err(e) => self.errors.push(e),
+        }
+
+        self
+    }
+
+    /// Defines a label that points at the next instruction.
+    pub fn label(&mut self, name: &str) -> &mut Self {
+        let address = self.context.current_address();
+        if let Err(e) = self.context.define_label(name, address) {
+            self.errors.push(e);
+        }
+
+        self
+    }
+
+    /// A label name that has not been returned before, such as `loop$3` for the prefix `loop`.
+    pub fn unique_label(&mut self, prefix: &str) -> String {
+        self.counter += 1;
+        format!("{}${}", prefix, self.counter)
+    }
+
+    /// Resolves every reference and returns the first error that was found.
+    pub fn build(self) -> Result<Vec<u16>, AssemblerError> {
+        self.build_program().map(|program| program.words)
+    }
+
+    /// Like [`ProgramBuilder::build`], but keeps the symbols of the program.
+    pub fn build_program(self) -> Result<AssembledProgram, AssemblerError> {
+        let Self {
+            mut context,
+            references,
+            errors,
+            ..
+        } = self;
+
+        if let Some(error) = errors.into_iter().next() {
+            return Err(error);
+        }
+
+        let mut resolved = Vec::new();
+        for (address, reference) in references {
+            let word = match reference {
+                Reference::Symbol(name) => context.get_or_create_variable(&name)?,
+                Reference::Expression(value) => AInstruction { value }.resolve(&mut context)?,
+            };
+            resolved.push((address, word));
+        }
+
+        let symbols = context.symbols();
+        let mut words = context.into_output();
+        for (address, word) in resolved {
+            words[address] = word;
+        }
+
+        Ok(AssembledProgram {
+            words,
+            sources: Vec::new(),
+            symbols,
+            optimization: None,
+        })
+    }
+}
+
+impl Default for ProgramBuilder<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Writes Hack assembly inside of Rust, checked while the Rust code is compiled.
+///
+/// Instructions are separated by `;`, which may be left out after labels, a-instructions and
+/// jumps, since they end unambiguously. `@{expr}` and `({expr})`
+/// refer to a symbol or define a label whose name is only known at runtime.
+/// Every instruction turns into a call of a [`crate::ProgramBuilder`], either a new one
+/// that is returned or the one named in front of `=>`:
+/// ```
+/// # use hack_asm::hack;
+/// let builder = hack! {
+///     @R0; D=M;
+///     (LOOP)
+///     @R1; M=M+1;
+///     D=D-1; @LOOP; D;JGT
+/// };
+/// assert_eq!(builder.build().unwrap().len(), 7);
+///
+/// let mut builder = hack_asm::ProgramBuilder::new();
+/// for variable in ["x", "y"] {
+///     hack!(builder => @{variable}; D=M; @SP; AM=M+1; A=A-1; M=D);
+/// }
+/// assert_eq!(builder.build().unwrap()[6..8], [17, 0xFC10]);
+/// ```
+///
+/// Only the spelling of the Hack specification is accepted, so `A+M`, `A|D`, `DM=` or `@70000`
+/// do not compile. Jump mnemonics and registers have to be uppercase.
+/// ```compile_fail
+/// let builder = hack_asm::hack! { D=A+M };
+/// ```
+/// ```compile_fail
+/// let builder = hack_asm::hack! { D=A|D };
+/// ```
+/// Every instruction adds to the recursion depth of the macro, so very long programs may
+/// need a higher `#![recursion_limit]`.
+#[macro_export]
+macro_rules! hack {
+    // separators are optional where an instruction ends unambiguously
+    (@__munch $b:ident ; $($rest:tt)*) => {
+        $crate::hack!(@__munch $b $($rest)*);
+    };
+
+    // labels
+    (@__munch $b:ident ( $label:ident ) $($rest:tt)*) => {
+        $b.label(stringify!($label));
+        $crate::hack!(@__munch $b $($rest)*);
+    };
+    (@__munch $b:ident ( { $label:expr } ) $($rest:tt)*) => {
+        $b.label(&$label);
+        $crate::hack!(@__munch $b $($rest)*);
+    };
+
+    // a-instructions
+    (@__munch $b:ident @ $symbol:ident $($rest:tt)*) => {
+        $b.at(stringify!($symbol));
+        $crate::hack!(@__munch $b $($rest)*);
+    };
+    (@__munch $b:ident @ { $value:expr } $($rest:tt)*) => {
+        $b.at(&$value);
+        $crate::hack!(@__munch $b $($rest)*);
+    };
+    (@__munch $b:ident @ $value:literal $($rest:tt)*) => {
+        $b.load({
+            const VALUE: u16 = $value;
+            const _: () = assert!(VALUE <= $crate::HackInt::MAX, "a-instructions load at most 32767");
+            VALUE
+        });
+        $crate::hack!(@__munch $b $($rest)*);
+    };
+
+    // c-instructions, whose computation is one to three tokens long
+    (@__munch $b:ident $dest:ident = $c1:tt $(; $($rest:tt)*)?) => {
+        $crate::hack!(@__jump $b [$dest] [$c1] $($($rest)*)?);
+    };
+    (@__munch $b:ident $dest:ident = $c1:tt $c2:tt $(; $($rest:tt)*)?) => {
+        $crate::hack!(@__jump $b [$dest] [$c1 $c2] $($($rest)*)?);
+    };
+    (@__munch $b:ident $dest:ident = $c1:tt $c2:tt $c3:tt $(; $($rest:tt)*)?) => {
+        $crate::hack!(@__jump $b [$dest] [$c1 $c2 $c3] $($($rest)*)?);
+    };
+    (@__munch $b:ident $c1:tt $(; $($rest:tt)*)?) => {
+        $crate::hack!(@__jump $b [] [$c1] $($($rest)*)?);
+    };
+    (@__munch $b:ident $c1:tt $c2:tt $(; $($rest:tt)*)?) => {
+        $crate::hack!(@__jump $b [] [$c1 $c2] $($($rest)*)?);
+    };
+    (@__munch $b:ident $c1:tt $c2:tt $c3:tt $(; $($rest:tt)*)?) => {
+        $crate::hack!(@__jump $b [] [$c1 $c2 $c3] $($($rest)*)?);
+    };
+    (@__munch $b:ident) => {};
+    (@__munch $b:ident $($invalid:tt)*) => {
+        compile_error!(concat!("expected an instruction, found \"", stringify!($($invalid)*), "\""))
+    };
+
+    // a jump mnemonic after the computation belongs to it, anything else is the next instruction
+    (@__jump $b:ident $dest:tt $comp:tt JMP $($rest:tt)*) => {
+        $crate::hack!(@__emit $b $dest $comp Jmp);
+        $crate::hack!(@__munch $b $($rest)*);
+    };
+    (@__jump $b:ident $dest:tt $comp:tt JGT $($rest:tt)*) => {
+        $crate::hack!(@__emit $b $dest $comp Jgt);
+        $crate::hack!(@__munch $b $($rest)*);
+    };
+    (@__jump $b:ident $dest:tt $comp:tt JEQ $($rest:tt)*) => {
+        $crate::hack!(@__emit $b $dest $comp Jeq);
+        $crate::hack!(@__munch $b $($rest)*);
+    };
+    (@__jump $b:ident $dest:tt $comp:tt JLT $($rest:tt)*) => {
+        $crate::hack!(@__emit $b $dest $comp Jlt);
+        $crate::hack!(@__munch $b $($rest)*);
+    };
+    (@__jump $b:ident $dest:tt $comp:tt JGE $($rest:tt)*) => {
+        $crate::hack!(@__emit $b $dest $comp Jge);
+        $crate::hack!(@__munch $b $($rest)*);
+    };
+    (@__jump $b:ident $dest:tt $comp:tt JLE $($rest:tt)*) => {
+        $crate::hack!(@__emit $b $dest $comp Jle);
+        $crate::hack!(@__munch $b $($rest)*);
+    };
+    (@__jump $b:ident $dest:tt $comp:tt JNE $($rest:tt)*) => {
+        $crate::hack!(@__emit $b $dest $comp Jne);
+        $crate::hack!(@__munch $b $($rest)*);
+    };
+    (@__jump $b:ident $dest:tt $comp:tt $($rest:tt)*) => {
+        $crate::hack!(@__emit $b $dest $comp);
+        $crate::hack!(@__munch $b $($rest)*);
+    };
+
+    (@__emit $b:ident [$($dest:ident)?] [$($comp:tt)*] $($jump:ident)?) => {
+        $b.c($crate::CInstruction {
+            destination: $crate::hack!(@__dest $($dest)?),
+            computation: $crate::hack!(@__comp $($comp)*),
+            jump: $crate::hack!(@__jump_type $($jump)?),
+        });
+    };
+
+    (@__jump_type) => { None };
+    (@__jump_type $jump:ident) => { Some($crate::JumpType::$jump) };
+
+    (@__dest) => { None };
+    (@__dest A) => { Some(vec![$crate::Register::A]) };
+    (@__dest M) => { Some(vec![$crate::Register::M]) };
+    (@__dest D) => { Some(vec![$crate::Register::D]) };
+    (@__dest AM) => { Some(vec![$crate::Register::A, $crate::Register::M]) };
+    (@__dest AD) => { Some(vec![$crate::Register::A, $crate::Register::D]) };
+    (@__dest MD) => { Some(vec![$crate::Register::M, $crate::Register::D]) };
+    (@__dest AMD) => {
+        Some(vec![$crate::Register::A, $crate::Register::M, $crate::Register::D])
+    };
+    (@__dest $($invalid:tt)*) => {
+        compile_error!(concat!(
+            "\"", stringify!($($invalid)*), "\" is not a destination of the Hack specification"
+        ))
+    };
+
+    (@__register A) => { $crate::Register::A };
+    (@__register M) => { $crate::Register::M };
+    (@__register D) => { $crate::Register::D };
+    (@__register $($invalid:tt)*) => {
+        compile_error!(concat!("expected A, M or D, found \"", stringify!($($invalid)*), "\""))
+    };
+    (@__address A) => { $crate::Register::A };
+    (@__address M) => { $crate::Register::M };
+    (@__address $($invalid:tt)*) => {
+        compile_error!(concat!(
+            "D can only be combined with A or M, not \"", stringify!($($invalid)*), "\""
+        ))
+    };
+
+    (@__comp 0) => { $crate::Computation::Literal(0) };
+    (@__comp 1) => { $crate::Computation::Literal(1) };
+    (@__comp - 1) => { $crate::Computation::Literal(-1) };
+    (@__comp ! $r:ident) => { $crate::Computation::Not($crate::hack!(@__register $r)) };
+    (@__comp - $r:ident) => { $crate::Computation::Neg($crate::hack!(@__register $r)) };
+    (@__comp $r:ident + 1) => { $crate::Computation::Inc($crate::hack!(@__register $r)) };
+    (@__comp $r:ident - 1) => { $crate::Computation::Dec($crate::hack!(@__register $r)) };
+    // every binary computation combines D with A or M, only subtraction may start with A or M
+    (@__comp D + $r:ident) => { $crate::hack!(@__binary Add D $r) };
+    (@__comp D & $r:ident) => { $crate::hack!(@__binary And D $r) };
+    (@__comp D | $r:ident) => { $crate::hack!(@__binary Or D $r) };
+    (@__comp D - $r:ident) => { $crate::hack!(@__binary Sub D $r) };
+    (@__comp $r:ident - D) => { $crate::hack!(@__binary Sub $r D) };
+    (@__comp $r:ident) => { $crate::Computation::Identity($crate::hack!(@__register $r)) };
+    (@__comp $($invalid:tt)*) => {
+        compile_error!(concat!(
+            "\"", stringify!($($invalid)*), "\" is not a computation the ALU can perform"
+        ))
+    };
+
+    (@__binary $op:ident D $r:ident) => {
+        $crate::Computation::$op($crate::Register::D, $crate::hack!(@__address $r))
+    };
+    (@__binary $op:ident $r:ident D) => {
+        $crate::Computation::$op($crate::hack!(@__address $r), $crate::Register::D)
+    };
+
+    ($b:ident => $($program:tt)*) => {{
+        $crate::hack!(@__munch $b $($program)*);
+    }};
+    ($($program:tt)*) => {{
+        let mut builder = $crate::ProgramBuilder::new();
+        $crate::hack!(@__munch builder $($program)*);
+        builder
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assembler::Assembler, assembler_context::AssemblerErrorKind};
+
+    #[test]
+    fn test_macro_assembles_like_the_assembler() {
+        let program = "@i\nM=1\n(LOOP)\n@i\nD=M\n@n\nD=D-M\n@END\nD;JGT\n@i\nM=M+1\nA=D|A\nAM=M-1;JNE\nMD=-1\n@LOOP\n0;JMP\n(END)\n@32767\n";
+        let expected = Assembler::new(program).assemble().unwrap();
+
+        let builder = crate::hack! {
+            @i; M=1;
+            (LOOP)
+            @i; D=M; @n; D=D-M; @END; D;JGT
+            @i; M=M+1; A=D|A; AM=M-1;JNE; MD=-1;
+            @LOOP; 0;JMP
+            (END)
+            @32767
+        };
+        assert_eq!(builder.build().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_names_known_at_runtime() {
+        let mut builder = ProgramBuilder::new();
+        let done = builder.unique_label("done");
+        crate::hack!(builder => @{done}; 0;JMP; ({done}) @{"x"});
+        let program = builder.build_program().unwrap();
+        assert_eq!(program.words, [2, 0xEA87, 16]);
+        assert_eq!(program.symbols.len(), 2);
+    }
+
+    #[test]
+    fn test_a_instructions() {
+        let mut builder = ProgramBuilder::new();
+        for reference in ["@SCREEN+1", "@x", "@7", "@END-1"] {
+            builder.a(crate::parsing::parse_reference(reference).unwrap());
+        }
+        builder.label("END");
+        assert_eq!(builder.build().unwrap(), [16385, 16, 7, 3]);
+    }
+
+    #[test]
+    fn test_errors_are_collected() {
+        let mut builder = ProgramBuilder::new();
+        builder.label("twice").label("twice").load(40000);
+        let error = builder.build().unwrap_err();
+        assert!(matches!(
+            error.kind,
+            AssemblerErrorKind::SymbolTableSetError(_)
+        ));
+    }
+
+    #[test]
+    fn test_options() {
+        let options = AssemblerOptions::new().with_rom_size(1);
+        let mut builder = ProgramBuilder::with_options(&options).unwrap();
+        crate::hack!(builder => D=0; D=1);
+        let error = builder.build().unwrap_err();
+        assert!(matches!(
+            error.kind,
+            AssemblerErrorKind::TooManyInstructions
+        ));
+    }
+}
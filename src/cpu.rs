@@ -0,0 +1,233 @@
+use thiserror::Error;
+
+use crate::symbol_table::BUILT_IN;
+
+#[derive(Error, Debug)]
+pub enum CpuError {
+    #[error("program with {0} instructions does not fit into the ROM")]
+    ProgramTooLarge(usize),
+}
+
+/// An emulated Hack CPU together with its ROM and RAM.
+///
+/// # Example
+/// ```
+/// # use hack_asm::{Assembler, Cpu};
+/// let program = Assembler::new("@2\nD=A\n@3\nD=D+A\n@0\nM=D\n").assemble().unwrap();
+/// let mut cpu = Cpu::new(&program).unwrap();
+///
+/// cpu.run(100);
+/// assert_eq!(cpu.ram()[0], 5);
+/// ```
+pub struct Cpu {
+    a: u16,
+    d: u16,
+    pc: u16,
+    rom: Box<[u16]>,
+    ram: Box<[u16]>,
+    program_len: usize,
+}
+
+impl Cpu {
+    pub const ROM_SIZE: usize = crate::constants::ROM_SIZE;
+    pub const RAM_SIZE: usize = 32768;
+
+    /// Creates a CPU with the program loaded into the ROM, every register and the RAM set to zero.
+    pub fn new(program: &[u16]) -> Result<Self, CpuError> {
+        if program.len() > Self::ROM_SIZE {
+            return Err(CpuError::ProgramTooLarge(program.len()));
+        }
+
+        let mut rom = vec![0; Self::ROM_SIZE].into_boxed_slice();
+        rom[..program.len()].copy_from_slice(program);
+
+        Ok(Self {
+            a: 0,
+            d: 0,
+            pc: 0,
+            rom,
+            ram: vec![0; Self::RAM_SIZE].into_boxed_slice(),
+            program_len: program.len(),
+        })
+    }
+
+    pub fn a(&self) -> u16 {
+        self.a
+    }
+
+    pub fn d(&self) -> u16 {
+        self.d
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn set_a(&mut self, value: u16) {
+        self.a = value;
+    }
+
+    pub fn set_d(&mut self, value: u16) {
+        self.d = value;
+    }
+
+    pub fn set_pc(&mut self, value: u16) {
+        self.pc = value & 0x7FFF;
+    }
+
+    pub fn ram(&self) -> &[u16] {
+        &self.ram
+    }
+
+    pub fn ram_mut(&mut self) -> &mut [u16] {
+        &mut self.ram
+    }
+
+    /// The memory map of the screen, one bit per pixel.
+    pub fn screen(&self) -> &[u16] {
+        let start = u16::from(BUILT_IN["SCREEN"]) as usize;
+        let end = u16::from(BUILT_IN["KBD"]) as usize;
+        &self.ram[start..end]
+    }
+
+    /// Sets the key code that is currently pressed, 0 means no key is pressed.
+    pub fn set_keyboard(&mut self, key: u16) {
+        self.ram[u16::from(BUILT_IN["KBD"]) as usize] = key;
+    }
+
+    /// Sets the program counter back to 0, RAM and registers keep their values.
+    pub fn reset(&mut self) {
+        self.pc = 0;
+    }
+
+    /// Whether the CPU ran past the end of the program or is stuck in an
+    /// infinite loop such as `(END) @END 0;JMP`.
+    pub fn is_halted(&self) -> bool {
+        let pc = self.pc as usize;
+        if pc >= self.program_len {
+            return true;
+        }
+
+        // an a-instruction loading its own address followed by an unconditional jump
+        const UNCONDITIONAL_JUMP: u16 = 0b1110_1010_1000_0111;
+        self.rom[pc] == self.pc && self.rom.get(pc + 1) == Some(&UNCONDITIONAL_JUMP)
+    }
+
+    /// The Hack ALU, `x` is always the D register while `y` is either A or M.
+    fn alu(x: u16, y: u16, control: u16) -> u16 {
+        let bit = |n: u16| control & (1 << n) != 0;
+
+        let x = if bit(5) { 0 } else { x };
+        let x = if bit(4) { !x } else { x };
+        let y = if bit(3) { 0 } else { y };
+        let y = if bit(2) { !y } else { y };
+        let out = if bit(1) { x.wrapping_add(y) } else { x & y };
+
+        if bit(0) {
+            !out
+        } else {
+            out
+        }
+    }
+
+    fn address(&self) -> usize {
+        // the address bus is only 15 bits wide
+        (self.a & 0x7FFF) as usize
+    }
+
+    /// Executes a single instruction.
+    pub fn step(&mut self) {
+        let instruction = self.rom[self.pc as usize];
+
+        if instruction & 0x8000 == 0 {
+            self.a = instruction;
+            self.pc = self.pc.wrapping_add(1) & 0x7FFF;
+            return;
+        }
+
+        let address = self.address();
+        let y = if instruction & 0x1000 != 0 {
+            self.ram[address]
+        } else {
+            self.a
+        };
+        let out = Self::alu(self.d, y, (instruction >> 6) & 0b111111);
+
+        let jump = match out as i16 {
+            0 => instruction & 0b010 != 0,
+            n if n < 0 => instruction & 0b100 != 0,
+            _ => instruction & 0b001 != 0,
+        };
+
+        // the jump target and the memory address are taken from A before it is overwritten
+        let target = self.a;
+
+        if instruction & 0b001_000 != 0 {
+            self.ram[address] = out;
+        }
+        if instruction & 0b010_000 != 0 {
+            self.d = out;
+        }
+        if instruction & 0b100_000 != 0 {
+            self.a = out;
+        }
+
+        self.pc = if jump {
+            target & 0x7FFF
+        } else {
+            self.pc.wrapping_add(1) & 0x7FFF
+        };
+    }
+
+    /// Executes instructions until the CPU halts or `max_cycles` instructions were executed.
+    /// Returns the number of executed instructions.
+    pub fn run(&mut self, max_cycles: usize) -> usize {
+        for cycle in 0..max_cycles {
+            if self.is_halted() {
+                return cycle;
+            }
+
+            self.step();
+        }
+
+        max_cycles
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Assembler;
+
+    fn run(program: &str, ram: &[(usize, u16)]) -> Cpu {
+        let program = Assembler::new(program).assemble().unwrap();
+        let mut cpu = Cpu::new(&program).unwrap();
+        for &(address, value) in ram {
+            cpu.ram_mut()[address] = value;
+        }
+
+        cpu.run(10_000);
+        assert!(cpu.is_halted());
+        cpu
+    }
+
+    #[test]
+    fn test_multiply() {
+        let program = "@R2\nM=0\n(LOOP)\n@R1\nD=M\n@END\nD;JEQ\n@R0\nD=M\n@R2\nM=D+M\n@R1\nM=M-1\n@LOOP\n0;JMP\n(END)\n@END\n0;JMP\n";
+        let cpu = run(program, &[(0, 7), (1, 6)]);
+
+        assert_eq!(cpu.ram()[2], 42);
+    }
+
+    #[test]
+    fn test_alu() {
+        let program = "@5\nD=-A\n@R0\nM=D\nD=!D\n@R1\nM=D\nAM=D-1\nD=A\n@R2\nMD=D|M\n";
+        let cpu = run(program, &[]);
+
+        assert_eq!(cpu.ram()[0], -5i16 as u16);
+        // AM=D-1 writes to RAM[1] since M is addressed by A before it changes
+        assert_eq!(cpu.ram()[1], 3);
+        assert_eq!(cpu.ram()[2], 3);
+        assert_eq!(cpu.d(), 3);
+    }
+}
@@ -0,0 +1,100 @@
+use std::fmt;
+
+use crate::{assembler_context::AssemblerError, location::Location};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// Additional context for a diagnostic, such as the macro call a line was expanded from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Note {
+    pub message: String,
+    pub location: Location,
+}
+
+impl Note {
+    pub(crate) fn render_all(f: &mut fmt::Formatter<'_>, notes: &[Note]) -> fmt::Result {
+        for note in notes {
+            writeln!(f)?;
+            note.location.render(f, "note", &note.message)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// An error or warning that was found while assembling a program.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub location: Option<Location>,
+    pub notes: Vec<Note>,
+}
+
+impl Diagnostic {
+    pub fn warning(message: String, location: Location) -> Self {
+        Self {
+            severity: Severity::Warning,
+            message,
+            location: Some(location),
+            notes: Vec::new(),
+        }
+    }
+
+    pub(crate) fn with_notes(mut self, notes: Vec<Note>) -> Self {
+        self.notes.extend(notes);
+        self
+    }
+
+    /// Sets the file name of the location and notes, if there is no file name yet.
+    pub fn with_file(mut self, file: &str) -> Self {
+        let notes = self.notes.iter_mut().map(|note| &mut note.location);
+
+        for location in self.location.iter_mut().chain(notes) {
+            location.file.get_or_insert_with(|| file.to_string());
+        }
+
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl From<AssemblerError> for Diagnostic {
+    fn from(error: AssemblerError) -> Self {
+        Self {
+            severity: Severity::Error,
+            message: error.kind.to_string(),
+            location: error.location.map(|location| *location),
+            notes: error.notes,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = self.severity.to_string();
+
+        match &self.location {
+            Some(location) => location.render(f, &severity, &self.message)?,
+            None => write!(f, "{}: {}", severity, self.message)?,
+        }
+
+        Note::render_all(f, &self.notes)
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use thiserror::Error;
+
+use crate::{
+    hack_int::HackInt,
+    instructions::{AInstruction, AValue, CInstruction},
+};
+
+#[derive(Error, Debug, PartialEq)]
+pub enum DecodeError {
+    #[error("bits 13 and 14 of the c-instruction {0:016b} are not set")]
+    InvalidPrefix(u16),
+    #[error("c-instruction {0:016b} does not encode a valid computation")]
+    InvalidComputation(u16),
+    #[error("{0:016b} is an a-instruction, not a c-instruction")]
+    NotCInstruction(u16),
+    #[error("{0:016b} is a c-instruction, not an a-instruction")]
+    NotAInstruction(u16),
+}
+
+/// A decoded word, a-instructions always hold a literal.
+pub enum DecodedInstruction {
+    AInstruction(AInstruction<'static>),
+    CInstruction(CInstruction),
+}
+
+/// Decodes a single 16-bit word into the instruction it encodes.
+pub fn decode(word: u16) -> Result<DecodedInstruction, DecodeError> {
+    if word & 0b1000_0000_0000_0000 == 0 {
+        let value = AValue::Literal(HackInt::new_unchecked(word));
+        return Ok(DecodedInstruction::AInstruction(AInstruction { value }));
+    }
+
+    if word & 0b0110_0000_0000_0000 != 0b0110_0000_0000_0000 {
+        return Err(DecodeError::InvalidPrefix(word));
+    }
+
+    CInstruction::from_u16(word)
+        .map(DecodedInstruction::CInstruction)
+        .ok_or(DecodeError::InvalidComputation(word))
+}
+
+/// The result of disassembling a whole program.
+pub struct Disassembly {
+    /// The reconstructed Hack assembly.
+    pub text: String,
+    /// Every word that could not be decoded, together with its address.
+    /// These words are emitted as comments inside of [`Disassembly::text`].
+    pub errors: Vec<(usize, DecodeError)>,
+}
+
+fn label_name(address: usize) -> String {
+    format!("L_{:04}", address)
+}
+
+/// Turns a program back into Hack assembly.
+///
+/// Every `@address` that is directly followed by a jump is treated as a jump target,
+/// so a label `(L_address)` is synthesized in front of the instruction at that address.
+pub fn disassemble(program: &[u16]) -> Disassembly {
+    let decoded: Vec<_> = program.iter().map(|&word| decode(word)).collect();
+
+    // a-instructions whose value is used as a jump target by the next instruction
+    let mut jump_sources = BTreeSet::new();
+    let mut targets = BTreeSet::new();
+
+    for (address, pair) in decoded.windows(2).enumerate() {
+        if let [Ok(DecodedInstruction::AInstruction(a)), Ok(DecodedInstruction::CInstruction(c))] =
+            pair
+        {
+            let AValue::Literal(target) = a.value else {
+                continue;
+            };
+            let target = u16::from(target) as usize;
+
+            if c.jump.is_some() && target <= program.len() {
+                jump_sources.insert(address);
+                targets.insert(target);
+            }
+        }
+    }
+
+    let mut text = String::new();
+    let mut errors = Vec::new();
+
+    for (address, instruction) in decoded.into_iter().enumerate() {
+        if targets.contains(&address) {
+            writeln!(text, "({})", label_name(address)).unwrap();
+        }
+
+        match instruction {
+            Ok(DecodedInstruction::AInstruction(a)) => match a.value {
+                AValue::Literal(value) if jump_sources.contains(&address) => {
+                    writeln!(text, "@{}", label_name(u16::from(value) as usize)).unwrap()
+                }
+                value => writeln!(text, "@{}", value).unwrap(),
+            },
+            Ok(DecodedInstruction::CInstruction(c)) => writeln!(text, "{}", c).unwrap(),
+            Err(e) => {
+                writeln!(text, "// invalid instruction at {}: {}", address, e).unwrap();
+                errors.push((address, e));
+            }
+        }
+    }
+
+    // a jump may target the address right after the last instruction
+    if targets.contains(&program.len()) {
+        writeln!(text, "({})", label_name(program.len())).unwrap();
+    }
+
+    Disassembly { text, errors }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Assembler;
+
+    #[test]
+    fn test_round_trip() {
+        let program = "@2\nD=A\n(L_0002)\nAM=M+1\nD;JGT\n@L_0002\n0;JMP\n(L_0006)\nMD=D|M\n@L_0006\nD-A;JNE\n";
+        let assembled = Assembler::new(program).assemble().unwrap();
+        let disassembly = disassemble(&assembled);
+
+        assert!(disassembly.errors.is_empty());
+        assert_eq!(disassembly.text, program);
+    }
+
+    #[test]
+    fn test_invalid_words() {
+        assert_eq!(
+            decode(0b1000_0000_0000_0000).err(),
+            Some(DecodeError::InvalidPrefix(0b1000_0000_0000_0000))
+        );
+        assert_eq!(
+            decode(0b1111_1111_1100_0000).err(),
+            Some(DecodeError::InvalidComputation(0b1111_1111_1100_0000))
+        );
+    }
+}
@@ -1,55 +1,186 @@
+use std::{fmt, num::IntErrorKind, str::FromStr};
+
 use thiserror::Error;
 
 /// A HackInt is an integer between 0 and 32767 (inclusive).
 /// So technically a u16 is one bit larger but it is an in-built type we can use.
 /// However, a HackInt shall always be inside of the aforementioned bounds.
 /// We assure the correctness of this by checking the user input inside of the parser.
-#[derive(PartialEq, PartialOrd, Copy, Clone)]
+///
+/// Every value an a-instruction can load is a HackInt.
+/// ```
+/// # use hack_asm::HackInt;
+/// let value: HackInt = "0x4000".parse().unwrap();
+/// assert_eq!(value, 16384);
+/// assert_eq!(HackInt::try_from(16384).unwrap(), value);
+/// assert!(HackInt::try_from(32768).is_err());
+/// ```
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone)]
 pub struct HackInt(u16);
 
 #[derive(Error, Debug)]
 pub enum ParseHackIntError {
-    #[error("number is not in bounds")]
-    SizeExceeded,
-    #[error("could not parse int")]
-    ParseInt(#[from] std::num::ParseIntError),
+    #[error("number {0} is not between 0 and {}", HackInt::MAX)]
+    SizeExceeded(u64),
+    #[error("number {0} does not fit into 16 bits")]
+    WordExceeded(i64),
+    #[error("character {0:?} is not an ASCII character")]
+    NotAscii(char),
+    #[error("number {0} does not fit into 16 bits")]
+    TooLarge(String),
+    #[error("could not parse int {literal}: {source}")]
+    ParseInt {
+        literal: String,
+        source: std::num::ParseIntError,
+    },
 }
 
 impl HackInt {
-    const MAX: u16 = 32767;
+    pub const MAX: u16 = 32767;
 
     pub fn try_new(value: u16) -> Result<Self, ParseHackIntError> {
         if value > Self::MAX {
-            return Err(ParseHackIntError::SizeExceeded);
+            return Err(ParseHackIntError::SizeExceeded(value.into()));
         }
 
         Ok(Self(value))
     }
 
+    /// Parses a decimal, hexadecimal (`0x7FFF`), binary (`0b0111`) or character (`'A'`) literal.
+    /// Numbers may contain `_` as a digit separator.
     pub fn parse(input: &str) -> Result<Self, ParseHackIntError> {
-        let value: u16 = input.parse()?;
-        Self::try_new(value)
+        let value = parse_unsigned(input)?;
+        if value > Self::MAX.into() {
+            return Err(ParseHackIntError::SizeExceeded(value));
+        }
+
+        Ok(Self(value as u16))
+    }
+
+    /// Like [`HackInt::parse`], but accepts any 16 bit word, optionally preceded by a `-`.
+    /// Negative numbers are returned in two's complement.
+    pub fn parse_word(input: &str) -> Result<u16, ParseHackIntError> {
+        let (negative, digits) = match input.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, input),
+        };
+
+        let value = parse_unsigned(digits)?;
+        match (negative, u16::try_from(value)) {
+            (false, Ok(value)) => Ok(value),
+            (true, Ok(value)) if value <= 32768 => Ok(value.wrapping_neg()),
+            (true, _) => Err(ParseHackIntError::WordExceeded(-(value as i64))),
+            (false, Err(_)) => Err(ParseHackIntError::WordExceeded(value as i64)),
+        }
     }
 
-    pub const fn new_unchecked(value: u16) -> HackInt {
+    pub(crate) const fn new_unchecked(value: u16) -> HackInt {
         Self(value)
     }
 
-    pub fn inc_unchecked(&mut self) {
+    pub(crate) fn inc_unchecked(&mut self) {
         self.0 += 1;
     }
 }
 
+/// Parses a literal without checking whether it fits into a HackInt.
+fn parse_unsigned(input: &str) -> Result<u64, ParseHackIntError> {
+    if let Some(c) = input
+        .strip_prefix('\'')
+        .and_then(|rest| rest.strip_suffix('\''))
+    {
+        let c = c.chars().next().unwrap_or_default();
+        if !c.is_ascii() {
+            return Err(ParseHackIntError::NotAscii(c));
+        }
+
+        return Ok(c as u64);
+    }
+
+    let digits = input.replace('_', "");
+    let (digits, radix) = match digits.get(..2) {
+        Some("0x" | "0X") => (&digits[2..], 16),
+        Some("0b" | "0B") => (&digits[2..], 2),
+        _ => (digits.as_str(), 10),
+    };
+
+    u64::from_str_radix(digits, radix).map_err(|e| match e.kind() {
+        IntErrorKind::PosOverflow => ParseHackIntError::TooLarge(input.to_string()),
+        _ => ParseHackIntError::ParseInt {
+            literal: input.to_string(),
+            source: e,
+        },
+    })
+}
+
+impl fmt::Display for HackInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 impl From<HackInt> for u16 {
     fn from(hack_int: HackInt) -> Self {
         hack_int.0
     }
 }
 
-impl TryInto<HackInt> for u16 {
+impl PartialEq<u16> for HackInt {
+    fn eq(&self, other: &u16) -> bool {
+        self.0 == *other
+    }
+}
+
+impl TryFrom<u16> for HackInt {
     type Error = ParseHackIntError;
 
-    fn try_into(self) -> Result<HackInt, Self::Error> {
-        HackInt::try_new(self)
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        HackInt::try_new(value)
+    }
+}
+
+impl FromStr for HackInt {
+    type Err = ParseHackIntError;
+
+    /// Same as [`HackInt::parse`].
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        HackInt::parse(input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_literals() {
+        let parse = |input| HackInt::parse(input).map(u16::from);
+
+        assert_eq!(parse("1_000").unwrap(), 1000);
+        assert_eq!(parse("0x7FFF").unwrap(), 32767);
+        assert_eq!(parse("0b0000_1111").unwrap(), 15);
+        assert_eq!(parse("'A'").unwrap(), 65);
+        assert!(matches!(
+            parse("0x8000"),
+            Err(ParseHackIntError::SizeExceeded(32768))
+        ));
+
+        assert_eq!(HackInt::parse_word("0xFFFF").unwrap(), 0xFFFF);
+        assert_eq!(HackInt::parse_word("-32768").unwrap(), 0x8000);
+        assert!(matches!(
+            HackInt::parse_word("-32769"),
+            Err(ParseHackIntError::WordExceeded(-32769))
+        ));
+    }
+
+    #[test]
+    fn test_literal_too_large_for_any_integer() {
+        let literal = "0x1_0000_0000_0000_0000";
+        let error = HackInt::parse(literal).unwrap_err();
+        assert!(matches!(&error, ParseHackIntError::TooLarge(l) if l == literal));
+        assert_eq!(
+            error.to_string(),
+            "number 0x1_0000_0000_0000_0000 does not fit into 16 bits"
+        );
     }
 }
@@ -1,18 +1,24 @@
 #![allow(clippy::unusual_byte_groupings)]
 
+use std::{borrow::Cow, fmt, ops::Range, str::FromStr};
+
 use crate::{
-    assembler_context::{AssemblerContext, AssemblerError},
+    assembler_context::{AssemblerContext, AssemblerError, AssemblerErrorKind},
+    disassembler::{decode, DecodeError, DecodedInstruction},
     hack_int::HackInt,
+    parsing::{parse_c_instruction, parse_computation, parse_reference, ParseError},
 };
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+/// A register a c-instruction computes with or stores into, `M` is the memory at `A`.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum Register {
     D,
     M,
     A,
 }
 
-#[derive(Debug, Clone, Copy)]
+/// The condition under which a c-instruction jumps to the address in `A`.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum JumpType {
     Jmp,
     Jgt,
@@ -23,7 +29,9 @@ pub enum JumpType {
     Jne,
 }
 
-#[derive(Debug, Clone, Copy)]
+/// What the ALU computes. Only the 28 computations in [`Computation::ALL`] can be encoded,
+/// see [`Computation::canonical`].
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum Computation {
     Literal(i8),
     Identity(Register),
@@ -37,13 +45,87 @@ pub enum Computation {
     Or(Register, Register),
 }
 
-#[derive(Debug)]
+/// A c-instruction such as `AM=M-1;JNE`.
+///
+/// It can be parsed from and displayed as text, and encoded into and decoded from its word:
+/// ```
+/// # use hack_asm::{CInstruction, Computation, Register};
+/// let instruction: CInstruction = "MD=M+1".parse().unwrap();
+/// assert_eq!(instruction.computation, Computation::Inc(Register::M));
+///
+/// let word = instruction.to_u16().unwrap();
+/// assert_eq!(word, 0b1111110111011000);
+/// assert_eq!(CInstruction::try_from(word).unwrap(), instruction);
+/// assert_eq!(instruction.to_string(), "MD=M+1");
+/// ```
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub struct CInstruction {
+    /// The registers the result is stored into, in the order they were written.
     pub destination: Option<Vec<Register>>,
     pub computation: Computation,
     pub jump: Option<JumpType>,
 }
 
+impl TryFrom<u16> for CInstruction {
+    type Error = DecodeError;
+
+    /// Decodes the word, which fails for a-instructions and invalid c-instructions.
+    fn try_from(word: u16) -> Result<Self, Self::Error> {
+        match decode(word)? {
+            DecodedInstruction::CInstruction(instruction) => Ok(instruction),
+            DecodedInstruction::AInstruction(_) => Err(DecodeError::NotCInstruction(word)),
+        }
+    }
+}
+
+impl FromStr for CInstruction {
+    type Err = AssemblerError;
+
+    /// Parses a c-instruction in the permissive dialect, so `dm=m+1` is the same as `MD=M+1`.
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        parse_c_instruction(input)
+    }
+}
+
+impl FromStr for Computation {
+    type Err = AssemblerError;
+
+    /// Parses a computation into its canonical spelling, so `A+D` is the same as `D+A`.
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        parse_computation(input)
+    }
+}
+
+impl FromStr for Register {
+    type Err = AssemblerError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_ascii_uppercase().as_str() {
+            "A" => Ok(Register::A),
+            "M" => Ok(Register::M),
+            "D" => Ok(Register::D),
+            _ => Err(ParseError::Syntax(format!("expected register, found \"{}\"", input)).into()),
+        }
+    }
+}
+
+impl FromStr for JumpType {
+    type Err = AssemblerError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_ascii_uppercase().as_str() {
+            "JMP" => Ok(JumpType::Jmp),
+            "JGT" => Ok(JumpType::Jgt),
+            "JEQ" => Ok(JumpType::Jeq),
+            "JLT" => Ok(JumpType::Jlt),
+            "JGE" => Ok(JumpType::Jge),
+            "JLE" => Ok(JumpType::Jle),
+            "JNE" => Ok(JumpType::Jne),
+            _ => Err(ParseError::Syntax(format!("expected jump, found \"{}\"", input)).into()),
+        }
+    }
+}
+
 impl CInstruction {
     fn jump_mask(jump: JumpType) -> u16 {
         match jump {
@@ -75,8 +157,9 @@ impl CInstruction {
         dest
     }
 
-    fn computation_mask(computation: Computation) -> u16 {
-        match computation {
+    /// Returns `None` for computations the ALU cannot perform.
+    fn computation_mask(computation: Computation) -> Option<u16> {
+        let mask = match computation.canonical()? {
             Computation::Literal(0) => 0b000_0101010_000000,
             Computation::Literal(1) => 0b000_0111111_000000,
             Computation::Literal(-1) => 0b000_0111010_000000,
@@ -95,10 +178,8 @@ impl CInstruction {
             Computation::Dec(Register::D) => 0b000_0001110_000000,
             Computation::Dec(Register::A) => 0b000_0110010_000000,
             Computation::Dec(Register::M) => 0b000_1110010_000000,
-            Computation::Add(Register::D, Register::A)
-            | Computation::Add(Register::A, Register::D) => 0b000_0000010_000000,
-            Computation::Add(Register::D, Register::M)
-            | Computation::Add(Register::M, Register::D) => 0b000_1000010_000000,
+            Computation::Add(Register::D, Register::A) => 0b000_0000010_000000,
+            Computation::Add(Register::D, Register::M) => 0b000_1000010_000000,
             Computation::Sub(Register::D, Register::A) => 0b000_0010011_000000,
             Computation::Sub(Register::A, Register::D) => 0b000_0000111_000000,
             Computation::Sub(Register::D, Register::M) => 0b000_1010011_000000,
@@ -107,13 +188,111 @@ impl CInstruction {
             Computation::And(Register::D, Register::M) => 0b000_1000000_000000,
             Computation::Or(Register::D, Register::A) => 0b000_0010101_000000,
             Computation::Or(Register::D, Register::M) => 0b000_1010101_000000,
-            _ => todo!(),
+            _ => return None,
+        };
+
+        Some(mask)
+    }
+}
+
+impl Computation {
+    /// Every computation in its canonical spelling, as listed in the Hack specification.
+    pub const ALL: [Computation; 28] = [
+        Computation::Literal(0),
+        Computation::Literal(1),
+        Computation::Literal(-1),
+        Computation::Identity(Register::D),
+        Computation::Identity(Register::A),
+        Computation::Identity(Register::M),
+        Computation::Not(Register::D),
+        Computation::Not(Register::A),
+        Computation::Not(Register::M),
+        Computation::Neg(Register::D),
+        Computation::Neg(Register::A),
+        Computation::Neg(Register::M),
+        Computation::Inc(Register::D),
+        Computation::Inc(Register::A),
+        Computation::Inc(Register::M),
+        Computation::Dec(Register::D),
+        Computation::Dec(Register::A),
+        Computation::Dec(Register::M),
+        Computation::Add(Register::D, Register::A),
+        Computation::Add(Register::D, Register::M),
+        Computation::Sub(Register::D, Register::A),
+        Computation::Sub(Register::A, Register::D),
+        Computation::Sub(Register::D, Register::M),
+        Computation::Sub(Register::M, Register::D),
+        Computation::And(Register::D, Register::A),
+        Computation::And(Register::D, Register::M),
+        Computation::Or(Register::D, Register::A),
+        Computation::Or(Register::D, Register::M),
+    ];
+
+    /// The spelling of the computation the Hack specification uses, such as `D+A` for `A+D`.
+    /// Returns `None` for computations the ALU cannot perform, like `A+M`, `D+D` or `2`.
+    pub fn canonical(self) -> Option<Self> {
+        let canonical = match self {
+            // addition, and and or are commutative, the specification always lists D first
+            Computation::Add(r, Register::D) if r != Register::D => {
+                Computation::Add(Register::D, r)
+            }
+            Computation::And(r, Register::D) if r != Register::D => {
+                Computation::And(Register::D, r)
+            }
+            Computation::Or(r, Register::D) if r != Register::D => Computation::Or(Register::D, r),
+            computation => computation,
+        };
+
+        Computation::ALL.contains(&canonical).then_some(canonical)
+    }
+}
+
+impl CInstruction {
+    const PREFIX: u16 = 0b111_0000000_000_000;
+    const COMPUTATION_BITS: u16 = 0b000_1111111_000_000;
+
+    /// Decodes the bits of a c-instruction, this is the inverse of [`CInstruction::to_u16`].
+    /// Returns `None` if the bits are not a valid c-instruction.
+    pub(crate) fn from_u16(bits: u16) -> Option<Self> {
+        if bits & Self::PREFIX != Self::PREFIX {
+            return None;
         }
+
+        let computation = Computation::ALL
+            .into_iter()
+            .find(|&c| CInstruction::computation_mask(c) == Some(bits & Self::COMPUTATION_BITS))?;
+
+        let destination: Vec<_> = [Register::A, Register::M, Register::D]
+            .into_iter()
+            .filter(|r| bits & CInstruction::register_mask(r) != 0)
+            .collect();
+
+        let jump = [
+            JumpType::Jgt,
+            JumpType::Jeq,
+            JumpType::Jge,
+            JumpType::Jlt,
+            JumpType::Jne,
+            JumpType::Jle,
+            JumpType::Jmp,
+        ]
+        .into_iter()
+        .find(|&j| CInstruction::jump_mask(j) == bits & 0b111);
+
+        Some(CInstruction {
+            destination: (!destination.is_empty()).then_some(destination),
+            computation,
+            jump,
+        })
     }
 }
 
 impl CInstruction {
-    pub fn to_u16(&self) -> u16 {
+    /// Encodes the instruction, which fails if the ALU cannot perform its computation.
+    pub fn to_u16(&self) -> Result<u16, AssemblerError> {
+        let computation = CInstruction::computation_mask(self.computation).ok_or_else(|| {
+            AssemblerError::from(ParseError::InvalidComputation(self.computation.to_string()))
+        })?;
         let mut instruction = 0b1110000000000000;
 
         if let Some(destination) = &self.destination {
@@ -124,31 +303,459 @@ impl CInstruction {
             instruction |= CInstruction::jump_mask(jump)
         }
 
-        instruction |= CInstruction::computation_mask(self.computation);
+        instruction |= computation;
+
+        Ok(instruction)
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Register::D => write!(f, "D"),
+            Register::M => write!(f, "M"),
+            Register::A => write!(f, "A"),
+        }
+    }
+}
+
+impl fmt::Display for JumpType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mnemonic = match self {
+            JumpType::Jmp => "JMP",
+            JumpType::Jgt => "JGT",
+            JumpType::Jeq => "JEQ",
+            JumpType::Jlt => "JLT",
+            JumpType::Jge => "JGE",
+            JumpType::Jle => "JLE",
+            JumpType::Jne => "JNE",
+        };
+
+        write!(f, "{}", mnemonic)
+    }
+}
+
+impl fmt::Display for Computation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Computation::Literal(value) => write!(f, "{}", value),
+            Computation::Identity(r) => write!(f, "{}", r),
+            Computation::Not(r) => write!(f, "!{}", r),
+            Computation::Neg(r) => write!(f, "-{}", r),
+            Computation::Inc(r) => write!(f, "{}+1", r),
+            Computation::Dec(r) => write!(f, "{}-1", r),
+            Computation::Add(a, b) => write!(f, "{}+{}", a, b),
+            Computation::Sub(a, b) => write!(f, "{}-{}", a, b),
+            Computation::And(a, b) => write!(f, "{}&{}", a, b),
+            Computation::Or(a, b) => write!(f, "{}|{}", a, b),
+        }
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = match self {
+            Operator::Add => "+",
+            Operator::Sub => "-",
+            Operator::Mul => "*",
+            Operator::Div => "/",
+            Operator::Rem => "%",
+            Operator::And => "&",
+            Operator::Or => "|",
+            Operator::Shl => "<<",
+            Operator::Shr => ">>",
+        };
+
+        write!(f, "{}", symbol)
+    }
+}
+
+impl<'a> fmt::Display for AValue<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AValue::Symbol { name, .. } => write!(f, "{}", name),
+            AValue::Literal(value) => write!(f, "{}", value),
+            AValue::Binary {
+                operator, lhs, rhs, ..
+            } => {
+                // nested expressions are always parenthesized, so no precedence is lost
+                let operand = |f: &mut fmt::Formatter<'_>, value: &AValue| match value {
+                    AValue::Binary { .. } => write!(f, "({})", value),
+                    _ => write!(f, "{}", value),
+                };
+
+                operand(f, lhs)?;
+                write!(f, " {} ", operator)?;
+                operand(f, rhs)
+            }
+        }
+    }
+}
+
+impl fmt::Display for CInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(destination) = &self.destination {
+            for register in destination {
+                write!(f, "{}", register)?;
+            }
+            write!(f, "=")?;
+        }
+
+        write!(f, "{}", self.computation)?;
+
+        if let Some(jump) = self.jump {
+            write!(f, ";{}", jump)?;
+        }
+
+        Ok(())
+    }
+}
 
-        instruction
+/// A binary operator of a constant expression.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    And,
+    Or,
+    Shl,
+    Shr,
+}
+
+impl Operator {
+    /// Applies the operator, returns `None` if the result does not fit into an `i64`.
+    fn apply(self, lhs: i64, rhs: i64) -> Option<i64> {
+        match self {
+            Operator::Add => lhs.checked_add(rhs),
+            Operator::Sub => lhs.checked_sub(rhs),
+            Operator::Mul => lhs.checked_mul(rhs),
+            Operator::Div => lhs.checked_div(rhs),
+            Operator::Rem => lhs.checked_rem(rhs),
+            Operator::And => Some(lhs & rhs),
+            Operator::Or => Some(lhs | rhs),
+            Operator::Shl => lhs.checked_shl(u32::try_from(rhs).ok()?),
+            Operator::Shr => lhs.checked_shr(u32::try_from(rhs).ok()?),
+        }
     }
 }
 
+/// The value of an a-instruction.
+#[derive(Debug, PartialEq, Clone)]
 pub enum AValue<'a> {
-    Symbol(&'a str),
+    Symbol {
+        name: Cow<'a, str>,
+        /// The byte range of the symbol in the parsed source.
+        span: Range<usize>,
+    },
     Literal(HackInt),
+    /// A constant expression such as `SCREEN+32*5`.
+    Binary {
+        operator: Operator,
+        lhs: Box<AValue<'a>>,
+        rhs: Box<AValue<'a>>,
+        span: Range<usize>,
+    },
 }
 
+impl<'a> AValue<'a> {
+    /// Every symbol the value refers to, in the order they appear.
+    pub fn symbols(&self) -> Vec<&str> {
+        match self {
+            AValue::Symbol { name, .. } => vec![name],
+            AValue::Literal(_) => Vec::new(),
+            AValue::Binary { lhs, rhs, .. } => {
+                let mut symbols = lhs.symbols();
+                symbols.extend(rhs.symbols());
+                symbols
+            }
+        }
+    }
+
+    /// Copies the names of the symbols, so the value no longer borrows the source.
+    pub fn into_owned(self) -> AValue<'static> {
+        match self {
+            AValue::Symbol { name, span } => AValue::Symbol {
+                name: Cow::Owned(name.into_owned()),
+                span,
+            },
+            AValue::Literal(value) => AValue::Literal(value),
+            AValue::Binary {
+                operator,
+                lhs,
+                rhs,
+                span,
+            } => AValue::Binary {
+                operator,
+                lhs: Box::new(lhs.into_owned()),
+                rhs: Box::new(rhs.into_owned()),
+                span,
+            },
+        }
+    }
+
+    /// Replaces the name of every symbol for which `rename` returns a new one.
+    pub(crate) fn rename_symbols(
+        &mut self,
+        rename: &mut impl FnMut(&str, &Range<usize>) -> Option<String>,
+    ) {
+        match self {
+            AValue::Symbol { name, span } => {
+                if let Some(renamed) = rename(name, span) {
+                    *name = Cow::Owned(renamed);
+                }
+            }
+            AValue::Literal(_) => (),
+            AValue::Binary { lhs, rhs, .. } => {
+                lhs.rename_symbols(rename);
+                rhs.rename_symbols(rename);
+            }
+        }
+    }
+
+    /// Evaluates the expression, every symbol inside of it has to be defined already.
+    fn evaluate(&self, context: &mut AssemblerContext) -> Result<i64, AssemblerError> {
+        match self {
+            AValue::Symbol { name, span } => context
+                .get_defined_symbol(name)
+                .map(i64::from)
+                .map_err(|e| e.with_range(span)),
+            AValue::Literal(value) => Ok(u16::from(*value).into()),
+            AValue::Binary {
+                operator,
+                lhs,
+                rhs,
+                span,
+            } => {
+                let lhs = lhs.evaluate(context)?;
+                let rhs = rhs.evaluate(context)?;
+
+                if matches!(operator, Operator::Div | Operator::Rem) && rhs == 0 {
+                    return Err(
+                        AssemblerError::new(AssemblerErrorKind::DivisionByZero).with_range(span)
+                    );
+                }
+
+                operator.apply(lhs, rhs).ok_or_else(|| {
+                    AssemblerError::new(AssemblerErrorKind::ExpressionOverflow).with_range(span)
+                })
+            }
+        }
+    }
+}
+
+/// An a-instruction such as `@LOOP` or `@SCREEN+32`.
+///
+/// Like a [`CInstruction`] it can be parsed from and displayed as text, and encoded into and
+/// decoded from its word. Only symbols that are built in can be encoded on their own:
+/// ```
+/// # use hack_asm::AInstruction;
+/// let instruction: AInstruction = "@SCREEN+32".parse().unwrap();
+/// assert_eq!(instruction.to_u16().unwrap(), 16416);
+/// assert_eq!(instruction.to_string(), "@SCREEN + 32");
+///
+/// let instruction = AInstruction::try_from(16416).unwrap();
+/// assert_eq!(instruction.to_string(), "@16416");
+/// assert!("@LOOP".parse::<AInstruction>().unwrap().to_u16().is_err());
+/// ```
+#[derive(Debug, PartialEq, Clone)]
 pub struct AInstruction<'a> {
     pub value: AValue<'a>,
 }
 
+impl TryFrom<u16> for AInstruction<'static> {
+    type Error = DecodeError;
+
+    /// Decodes the word, which fails for c-instructions.
+    fn try_from(word: u16) -> Result<Self, Self::Error> {
+        let value = HackInt::try_new(word).map_err(|_| DecodeError::NotAInstruction(word))?;
+
+        Ok(AInstruction {
+            value: AValue::Literal(value),
+        })
+    }
+}
+
+impl FromStr for AInstruction<'static> {
+    type Err = AssemblerError;
+
+    /// Parses an a-instruction, the names of its symbols are copied out of the input.
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        parse_reference(input).map(AInstruction::into_owned)
+    }
+}
+
+impl<'a> fmt::Display for AInstruction<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "@{}", self.value)
+    }
+}
+
 impl<'a> AInstruction<'a> {
-    pub fn to_u16(&self, context: &mut AssemblerContext) -> Result<u16, AssemblerError> {
+    /// Copies the names of the symbols, so the instruction no longer borrows the source.
+    pub fn into_owned(self) -> AInstruction<'static> {
+        AInstruction {
+            value: self.value.into_owned(),
+        }
+    }
+
+    /// Encodes the instruction on its own, which fails for symbols that are not built in,
+    /// since labels and variables only get their value while a program is assembled.
+    pub fn to_u16(&self) -> Result<u16, AssemblerError> {
+        let mut context = AssemblerContext::default();
+        for name in self.value.symbols() {
+            context.get_defined_symbol(name)?;
+        }
+
+        self.resolve(&mut context)
+    }
+
+    /// Encodes the instruction with the symbols of a program, unknown symbols become variables.
+    pub(crate) fn resolve(&self, context: &mut AssemblerContext) -> Result<u16, AssemblerError> {
         match &self.value {
-            AValue::Symbol(name) => Ok(context.get_or_create_variable(name)?),
+            AValue::Symbol { name, span } => context
+                .get_or_create_variable(name)
+                .map_err(|e| e.with_range(span)),
             AValue::Literal(value) => Ok((*value).into()),
+            AValue::Binary { span, .. } => {
+                let value = self.value.evaluate(context)?;
+
+                u16::try_from(value)
+                    .ok()
+                    .filter(|&value| value <= HackInt::MAX)
+                    .ok_or_else(|| {
+                        AssemblerError::new(AssemblerErrorKind::ExpressionOutOfRange(value))
+                            .with_range(span)
+                    })
+            }
         }
     }
 }
 
+/// A constant defined by `.equ` or `.define`.
 #[derive(Debug)]
-pub struct Label<'a> {
+pub struct Constant<'a> {
     pub name: &'a str,
+    pub value: HackInt,
+    /// The byte range of the name in the parsed source.
+    pub span: Range<usize>,
+}
+
+/// A label defined by the program.
+#[derive(Debug)]
+pub struct Label<'a> {
+    /// The fully qualified name, local and numeric labels already include their scope.
+    pub name: Cow<'a, str>,
+    /// The byte range of the name in the parsed source.
+    pub span: Range<usize>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_instruction_round_trips() {
+        // every combination of computation, destination and jump
+        for word in 0b1110_0000_0000_0000..=u16::MAX {
+            let Ok(instruction) = CInstruction::try_from(word) else {
+                continue;
+            };
+            let parsed: CInstruction = instruction.to_string().parse().unwrap();
+
+            assert_eq!(parsed, instruction);
+            assert_eq!(parsed.to_u16().unwrap(), word);
+        }
+
+        for computation in Computation::ALL {
+            assert_eq!(
+                computation.to_string().parse::<Computation>().unwrap(),
+                computation
+            );
+        }
+        assert_eq!(
+            "A+D".parse::<Computation>().unwrap(),
+            Computation::Add(Register::D, Register::A)
+        );
+        assert!("A+M".parse::<Computation>().is_err());
+        assert!("D=M;".parse::<CInstruction>().is_err());
+        assert_eq!("jne".parse::<JumpType>().unwrap(), JumpType::Jne);
+        assert!(matches!(
+            CInstruction::try_from(0x0010),
+            Err(DecodeError::NotCInstruction(0x0010))
+        ));
+    }
+
+    #[test]
+    fn test_a_instruction_round_trips() {
+        for word in 0..0x8000 {
+            let instruction = AInstruction::try_from(word).unwrap();
+            let parsed: AInstruction = instruction.to_string().parse().unwrap();
+
+            assert_eq!(parsed, instruction);
+            assert_eq!(parsed.to_u16().unwrap(), word);
+        }
+
+        let screen: AInstruction = "@SCREEN+32".parse().unwrap();
+        assert_eq!(screen.to_u16().unwrap(), 16416);
+        assert!("@LOOP".parse::<AInstruction>().unwrap().to_u16().is_err());
+        assert!("D=M".parse::<AInstruction>().is_err());
+        assert!(matches!(
+            AInstruction::try_from(0x8000),
+            Err(DecodeError::NotAInstruction(0x8000))
+        ));
+    }
+
+    #[test]
+    fn test_literal_the_alu_cannot_compute() {
+        let literal = CInstruction {
+            destination: None,
+            computation: Computation::Literal(2),
+            jump: None,
+        };
+        assert!(literal.to_u16().is_err());
+    }
+
+    fn assemble(program: &str) -> Result<Vec<u16>, AssemblerError> {
+        crate::Assembler::new(program).assemble()
+    }
+
+    #[test]
+    fn test_expression_precedence() {
+        assert_eq!(assemble(".equ ROWS 5\n@SCREEN+32*ROWS\n").unwrap(), [16544]);
+    }
+
+    #[test]
+    fn test_expression_with_a_later_label() {
+        assert_eq!(assemble("D=0\n@(END-1)<<2\n(END)\n").unwrap(), [0xEA90, 4]);
+    }
+
+    #[test]
+    fn test_expression_out_of_range() {
+        let diagnostics = crate::Assembler::new("(END)\n@END*0-1\n")
+            .assemble_with_diagnostics()
+            .unwrap_err();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].message,
+            "expression evaluates to -1, which is not between 0 and 32767"
+        );
+    }
+
+    #[test]
+    fn test_division_by_zero() {
+        let error = assemble("@SCREEN+1/0\n").unwrap_err();
+        assert!(matches!(error.kind, AssemblerErrorKind::DivisionByZero));
+        // the error points at the division inside of the expression
+        let location = error.location.unwrap();
+        assert_eq!((location.column, location.length), (9, 3));
+    }
+
+    #[test]
+    fn test_remainder_is_not_part_of_a_symbol() {
+        let words = assemble("@0\n@0\n@0\n@0\n(X)\n@X%3\n").unwrap();
+        assert_eq!(words[4], 1);
+    }
 }
@@ -1,19 +1,61 @@
-#![feature(hash_raw_entry)]
 #[macro_use]
 extern crate pest_derive;
 
 mod assembler;
 mod assembler_context;
+mod builder;
+mod cpu;
+mod diagnostic;
+mod disassembler;
 mod hack_int;
 mod instructions;
+mod listing;
+mod location;
+mod object;
+mod optimizer;
+mod options;
+mod output;
 mod parsing;
+mod rom;
+mod rom_image;
 mod symbol_table;
+#[cfg(test)]
+mod test_dir;
+pub mod test_script;
+pub mod vm;
 
-pub use assembler::Assembler;
+pub use assembler::{AssembledProgram, Assembler};
+pub use assembler_context::{AssemblerError, AssemblerErrorKind};
+pub use builder::ProgramBuilder;
+pub use cpu::{Cpu, CpuError};
+pub use diagnostic::{Diagnostic, Note, Severity};
+pub use disassembler::{decode, disassemble, DecodeError, DecodedInstruction, Disassembly};
+pub use hack_int::{HackInt, ParseHackIntError};
+pub use instructions::{
+    AInstruction, AValue, CInstruction, Computation, Constant, JumpType, Label, Operator, Register,
+};
+pub use listing::{listing, symbols_json, symbols_text};
+pub use location::Location;
+pub use object::{link, LinkError, ObjectError, ObjectFile, ObjectWord};
+pub use optimizer::{
+    NoOp, OptimizationReport, Optimizer, OverwrittenLoad, PeepholeRule, RedundantReload,
+    UnreachableCode,
+};
+pub use options::{AssemblerOptions, Dialect, OptionsError};
+pub use output::{Annotated, Decimal, Endianness, Hack, HexWords, OutputFormat, RawBinary};
+pub use parsing::{
+    parse_str, IncludeError, MacroError, ParseError, ParsedInstruction, ParserOutput,
+};
+pub use rom::{read_hack, HackRom, RomError, RomFormat};
+pub use rom_image::{Coe, Depth, IntelHex, Logisim, Mif, Radix, Verilog, Vhdl};
+pub use symbol_table::{Symbol, SymbolKind, SymbolTable, SymbolTableGetError, SymbolTableSetError};
+pub use test_script::{run_test_script, TestReport, TestScriptError};
 
 mod constants {
-    use crate::hack_int::HackInt;
+    use std::ops::Range;
 
-    pub(crate) const MEMORY_SIZE: HackInt = HackInt::new_unchecked(16383);
-    pub(crate) const ROM_SIZE: usize = 32767;
+    /// The addresses between the virtual registers and the screen.
+    pub(crate) const VARIABLES: Range<u16> = 16..16384;
+    /// The number of instructions the ROM holds, at the addresses an a-instruction can load.
+    pub(crate) const ROM_SIZE: usize = 32768;
 }
@@ -0,0 +1,216 @@
+use std::fmt::Write;
+
+use crate::{
+    assembler::AssembledProgram,
+    disassembler::{decode, DecodedInstruction},
+    symbol_table::{Symbol, SymbolKind},
+};
+
+pub(crate) fn instruction_text(word: u16) -> String {
+    match decode(word) {
+        Ok(DecodedInstruction::AInstruction(a)) => format!("@{}", a.value),
+        Ok(DecodedInstruction::CInstruction(c)) => c.to_string(),
+        Err(_) => "??".to_string(),
+    }
+}
+
+/// Renders the address, the binary and hex word, the decoded instruction and
+/// the source line of every word side by side.
+/// Labels are listed in front of the address they resolved to.
+///
+/// ```text
+/// ADDR   BINARY            HEX   INSTRUCTION       LINE  SOURCE
+///                                (LOOP)
+/// 00002  0000000000010000  0010  @16                  4  @i
+/// ```
+pub fn listing(program: &AssembledProgram) -> String {
+    let mut text = String::new();
+    writeln!(
+        text,
+        "ADDR   BINARY            HEX   INSTRUCTION       LINE  SOURCE"
+    )
+    .unwrap();
+
+    let mut labels = program
+        .symbols
+        .iter()
+        .filter(|s| s.kind == SymbolKind::Label)
+        .peekable();
+    let mut previous_line = None;
+
+    for (address, (&word, source)) in program.words.iter().zip(&program.sources).enumerate() {
+        while let Some(label) = labels.next_if(|l| l.value as usize <= address) {
+            writeln!(text, "{:31}({})", "", label.name).unwrap();
+        }
+
+        write!(
+            text,
+            "{:05}  {:016b}  {:04X}  {:<16}",
+            address,
+            word,
+            word,
+            instruction_text(word)
+        )
+        .unwrap();
+
+        // a source line is only shown next to the first word it produced
+        let line = (&source.file, source.line);
+        if previous_line != Some(line) {
+            write!(text, "  {:>4}  {}", source.line, source.source_line.trim()).unwrap();
+            previous_line = Some(line);
+        }
+
+        writeln!(text).unwrap();
+    }
+
+    for label in labels {
+        writeln!(text, "{:31}({})", "", label.name).unwrap();
+    }
+
+    text
+}
+
+/// Lists every symbol with its value and kind, one per line.
+pub fn symbols_text(symbols: &[Symbol]) -> String {
+    let width = symbols.iter().map(|s| s.name.len()).max().unwrap_or(0);
+    let mut text = String::new();
+
+    for symbol in symbols {
+        writeln!(
+            text,
+            "{:width$}  {:>5}  {}",
+            symbol.name,
+            symbol.value,
+            symbol.kind,
+            width = width
+        )
+        .unwrap();
+    }
+
+    text
+}
+
+fn json_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => write!(escaped, "\\u{:04x}", c as u32).unwrap(),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped.push('"');
+    escaped
+}
+
+/// Lists every symbol as a JSON array of objects with a name, value and kind.
+pub fn symbols_json(symbols: &[Symbol]) -> String {
+    let entries: Vec<_> = symbols
+        .iter()
+        .map(|s| {
+            format!(
+                "  {{ \"name\": {}, \"value\": {}, \"kind\": \"{}\" }}",
+                json_string(&s.name),
+                s.value,
+                s.kind
+            )
+        })
+        .collect();
+
+    if entries.is_empty() {
+        "[]\n".to_string()
+    } else {
+        format!("[\n{}\n]\n", entries.join(",\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Assembler;
+
+    fn symbol(name: &str, value: u16, kind: SymbolKind) -> Symbol {
+        Symbol {
+            name: name.to_string(),
+            value,
+            kind,
+        }
+    }
+
+    #[test]
+    fn test_listing_columns() {
+        let program = "(LOOP)\n@i\nM=1 // set i\n@LOOP\n0;JMP\n(END)\n";
+        let program = Assembler::new(program).assemble_program().unwrap();
+
+        assert_eq!(
+            listing(&program),
+            concat!(
+                "ADDR   BINARY            HEX   INSTRUCTION       LINE  SOURCE\n",
+                "                               (LOOP)\n",
+                "00000  0000000000010000  0010  @16                  2  @i\n",
+                "00001  1110111111001000  EFC8  M=1                  3  M=1 // set i\n",
+                "00002  0000000000000000  0000  @0                   4  @LOOP\n",
+                "00003  1110101010000111  EA87  0;JMP                5  0;JMP\n",
+                "                               (END)\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_listing_shows_source_once() {
+        let program = Assembler::new("pop D\n").assemble_program().unwrap();
+        let listing = listing(&program);
+        let lines: Vec<_> = listing.lines().skip(1).map(str::trim_end).collect();
+
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].ends_with("   1  pop D"));
+        assert!(lines[1].ends_with("AM=M-1"));
+        assert!(lines[2].ends_with("D=M"));
+    }
+
+    #[test]
+    fn test_symbols_text() {
+        let symbols = [
+            symbol("LOOP", 2, SymbolKind::Label),
+            symbol("counter", 16, SymbolKind::Variable),
+        ];
+
+        assert_eq!(
+            symbols_text(&symbols),
+            "LOOP         2  label\ncounter     16  variable\n"
+        );
+    }
+
+    #[test]
+    fn test_symbols_json() {
+        let symbols = [
+            symbol("LOOP", 2, SymbolKind::Label),
+            symbol("ROWS", 5, SymbolKind::Constant),
+        ];
+
+        assert_eq!(
+            symbols_json(&symbols),
+            concat!(
+                "[\n",
+                "  { \"name\": \"LOOP\", \"value\": 2, \"kind\": \"label\" },\n",
+                "  { \"name\": \"ROWS\", \"value\": 5, \"kind\": \"constant\" }\n",
+                "]\n",
+            )
+        );
+        assert_eq!(symbols_json(&[]), "[]\n");
+    }
+
+    #[test]
+    fn test_symbols_json_escapes_names() {
+        let symbols = [symbol("a\"b\\c\n", 0, SymbolKind::Label)];
+
+        assert_eq!(
+            symbols_json(&symbols),
+            "[\n  { \"name\": \"a\\\"b\\\\c\\u000a\", \"value\": 0, \"kind\": \"label\" }\n]\n"
+        );
+    }
+}
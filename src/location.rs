@@ -0,0 +1,164 @@
+use std::{fmt, ops::Range};
+
+use pest::Span;
+
+/// A resolved position inside of a source file.
+/// Stores everything that is needed to point at the offending piece of code,
+/// so it can outlive the source text it was created from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    pub file: Option<String>,
+    pub line: usize,
+    pub column: usize,
+    pub source_line: String,
+    pub length: usize,
+}
+
+impl Location {
+    pub(crate) fn from_span(span: &Span) -> Self {
+        let start = span.start_pos();
+        let (line, column) = start.line_col();
+        let source_line = start.line_of().trim_end_matches(['\r', '\n']).to_string();
+
+        // only the first line of a multi line span is underlined
+        let remaining = source_line.chars().count().saturating_sub(column - 1);
+        let length = span.as_str().chars().count().min(remaining).max(1);
+
+        Self {
+            file: None,
+            line,
+            column,
+            source_line,
+            length,
+        }
+    }
+
+    pub(crate) fn from_line_col(input: &str, line: usize, column: usize) -> Self {
+        let source_line = input
+            .lines()
+            .nth(line - 1)
+            .unwrap_or_default()
+            .trim_end_matches('\r')
+            .to_string();
+
+        Self {
+            file: None,
+            line,
+            column,
+            source_line,
+            length: 1,
+        }
+    }
+
+    pub(crate) fn from_line_index(input: &str, index: &LineIndex, offset: usize) -> Self {
+        let line = index.line(offset);
+        let start = index.starts[line - 1];
+        let source_line = input[start..]
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .trim_end_matches('\r');
+
+        Self {
+            file: None,
+            line,
+            column: input[start..offset].chars().count() + 1,
+            source_line: source_line.to_string(),
+            length: 1,
+        }
+    }
+
+    /// The location of a byte range of the input, only its first line is underlined.
+    pub(crate) fn from_range(input: &str, index: &LineIndex, range: Range<usize>) -> Self {
+        let mut location = Self::from_line_index(input, index, range.start);
+        let remaining = location.source_line.chars().count() + 1 - location.column;
+        location.length = input[range].chars().count().min(remaining).max(1);
+
+        location
+    }
+
+    /// Renders a message together with this location the way rustc does:
+    /// ```text
+    /// error: tried to redefine the symbol "LOOP"
+    ///  --> program.asm:3:2
+    ///   |
+    /// 3 | (LOOP)
+    ///   |  ^^^^
+    /// ```
+    pub fn render(&self, f: &mut fmt::Formatter, severity: &str, message: &str) -> fmt::Result {
+        let gutter = " ".repeat(self.line.to_string().len());
+
+        writeln!(f, "{}: {}", severity, message)?;
+        match &self.file {
+            Some(file) => writeln!(f, "{}--> {}:{}:{}", gutter, file, self.line, self.column)?,
+            None => writeln!(f, "{}--> {}:{}", gutter, self.line, self.column)?,
+        }
+        writeln!(f, "{} |", gutter)?;
+        writeln!(f, "{} | {}", self.line, self.source_line)?;
+
+        // keep tabs so that the caret lines up with the source line
+        let padding: String = self
+            .source_line
+            .chars()
+            .take(self.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        write!(f, "{} | {}{}", gutter, padding, "^".repeat(self.length))
+    }
+}
+
+/// The byte offsets at which the lines of an input start.
+/// Allows looking up many lines without scanning the whole input every time.
+pub(crate) struct LineIndex {
+    starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(input: &str) -> Self {
+        let starts = std::iter::once(0)
+            .chain(input.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+
+        Self { starts }
+    }
+
+    /// The 1-based line number of a byte offset.
+    pub fn line(&self, offset: usize) -> usize {
+        self.starts.partition_point(|&start| start <= offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Rendered<'a>(&'a Location);
+
+    impl fmt::Display for Rendered<'_> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            self.0.render(f, "error", "something went wrong")
+        }
+    }
+
+    #[test]
+    fn test_render_points_at_span() {
+        let input = "@1\n  (LOOP)\n";
+        let span = Span::new(input, 6, 10).unwrap();
+        let mut location = Location::from_span(&span);
+        location.file = Some("test.asm".to_string());
+
+        assert_eq!((location.line, location.column, location.length), (2, 4, 4));
+        assert_eq!(
+            Rendered(&location).to_string(),
+            "error: something went wrong\n --> test.asm:2:4\n  |\n2 |   (LOOP)\n  |    ^^^^"
+        );
+    }
+
+    #[test]
+    fn test_range_of_the_input() {
+        let input = "@1\n  (LOOP)\n";
+        let location = Location::from_range(input, &LineIndex::new(input), 6..10);
+        assert_eq!((location.line, location.column, location.length), (2, 4, 4));
+        assert_eq!(location.source_line, "  (LOOP)");
+    }
+}
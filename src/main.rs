@@ -1,57 +1,783 @@
-use std::{fs::read_to_string, io::Write, path::PathBuf};
+use std::{fs::read_to_string, ops::Range, path::PathBuf, process::ExitCode};
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
 /// Simple program to greet a person
 #[derive(Parser, Debug)]
-#[clap(author, version, about, long_about = None)]
+#[clap(author, version, about, long_about = None, args_conflicts_with_subcommands = true)]
 struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    #[clap(flatten)]
+    assemble: AssembleArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct AssembleArgs {
     /// Input File
-    #[clap()]
-    input_file: String,
+    #[clap(required = true)]
+    input_file: Option<String>,
+
+    /// Report every error and warning instead of stopping at the first error
+    #[clap(long)]
+    diagnostics: bool,
+
+    /// Write a listing with the address, word and source line of every instruction to a .lst file
+    #[clap(long)]
+    listing: bool,
+
+    /// Write every label and variable to a .sym (text) or .json file
+    #[clap(long, value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "text")]
+    symbols: Option<SymbolFormat>,
+
+    /// The format of the assembled program, the file extension depends on it
+    #[clap(long, value_enum, default_value = "hack")]
+    format: OutputFormatArg,
+
+    /// Pad ROM images (ihex, verilog-bin, verilog-hex, mif, coe, vhdl, logisim) to this many words
+    #[clap(long, value_name = "WORDS")]
+    rom_depth: Option<usize>,
+
+    /// Cut off the words of a program that does not fit into --rom-depth instead of failing
+    #[clap(long, requires = "rom_depth")]
+    truncate: bool,
+
+    /// Directory to search for included files that are not found next to the including file
+    #[clap(short = 'I', long = "include-dir")]
+    include_dirs: Vec<PathBuf>,
+
+    /// Write a relocatable .o object file to be linked with `link` instead of a .hack file
+    #[clap(short = 'c', long, conflicts_with_all = ["diagnostics", "listing", "symbols", "format", "rom_depth", "truncate"])]
+    object: bool,
+
+    /// Reject pseudo-instructions such as `goto`, `push` or `inc`
+    #[clap(long)]
+    no_pseudo: bool,
+
+    /// Only accept the spelling of the Hack specification, such as `MD=` but not `DM=` or `md=`
+    #[clap(long)]
+    strict: bool,
+
+    /// Remove wasted instructions with the peephole optimizer and report the saved words
+    #[clap(short = 'O', long)]
+    optimize: bool,
+
+    /// Read the memory layout, predefined symbols and external constants from a config file
+    #[clap(long, value_name = "FILE")]
+    config: Option<String>,
 }
 
-fn main() {
-    let args = Args::parse();
+/// The arguments that change how a program is assembled.
+#[derive(Default)]
+struct Settings<'a> {
+    include_dirs: &'a [PathBuf],
+    no_pseudo: bool,
+    options: hack_asm::AssemblerOptions,
+    optimize: bool,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum SymbolFormat {
+    Text,
+    Json,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum OutputFormatArg {
+    /// One 16 digit binary number per line (.hack)
+    Hack,
+    /// Raw big-endian ROM image (.bin)
+    Bin,
+    /// Raw little-endian ROM image (.bin)
+    BinLe,
+    /// One 4 digit hexadecimal number per line (.hex)
+    Hex,
+    /// One decimal number per line (.dec)
+    Dec,
+    /// .hack with the address, instruction and source line as comments (.annotated.hack)
+    Annotated,
+    /// Intel HEX with word addresses (.ihx)
+    Ihex,
+    /// Memory file for Verilog's $readmemb (.mem)
+    VerilogBin,
+    /// Memory file for Verilog's $readmemh (.mem)
+    VerilogHex,
+    /// Altera memory initialization file (.mif)
+    Mif,
+    /// Xilinx coefficient file (.coe)
+    Coe,
+    /// VHDL package with a ROM constant (.vhd)
+    Vhdl,
+    /// Logisim and Digital "v2.0 raw" image (.rom)
+    Logisim,
+}
+
+impl OutputFormatArg {
+    /// The format files with this extension are written in, the opposite of
+    /// [`hack_asm::OutputFormat::extension`].
+    fn from_extension(path: &std::path::Path) -> Option<Self> {
+        let format = match path.extension()?.to_str()? {
+            "hack" => OutputFormatArg::Hack,
+            "bin" => OutputFormatArg::Bin,
+            "hex" => OutputFormatArg::Hex,
+            "dec" => OutputFormatArg::Dec,
+            "ihx" => OutputFormatArg::Ihex,
+            "mem" => OutputFormatArg::VerilogBin,
+            "mif" => OutputFormatArg::Mif,
+            "coe" => OutputFormatArg::Coe,
+            "vhd" => OutputFormatArg::Vhdl,
+            "rom" => OutputFormatArg::Logisim,
+            _ => return None,
+        };
+
+        Some(format)
+    }
+
+    fn format(self, depth: hack_asm::Depth) -> Box<dyn hack_asm::OutputFormat> {
+        let verilog = |radix| Box::new(hack_asm::Verilog { radix, depth });
+
+        match self {
+            OutputFormatArg::Hack => Box::new(hack_asm::Hack),
+            OutputFormatArg::Bin => Box::new(hack_asm::RawBinary(hack_asm::Endianness::Big)),
+            OutputFormatArg::BinLe => Box::new(hack_asm::RawBinary(hack_asm::Endianness::Little)),
+            OutputFormatArg::Hex => Box::new(hack_asm::HexWords),
+            OutputFormatArg::Dec => Box::new(hack_asm::Decimal),
+            OutputFormatArg::Annotated => Box::new(hack_asm::Annotated),
+            OutputFormatArg::Ihex => Box::new(hack_asm::IntelHex { depth }),
+            OutputFormatArg::VerilogBin => verilog(hack_asm::Radix::Binary),
+            OutputFormatArg::VerilogHex => verilog(hack_asm::Radix::Hex),
+            OutputFormatArg::Mif => Box::new(hack_asm::Mif { depth }),
+            OutputFormatArg::Coe => Box::new(hack_asm::Coe { depth }),
+            OutputFormatArg::Vhdl => Box::new(hack_asm::Vhdl { depth }),
+            OutputFormatArg::Logisim => Box::new(hack_asm::Logisim { depth }),
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Turn a .hack file or another ROM image back into Hack assembly
+    Disasm {
+        /// Input File
+        input_file: String,
+
+        /// Output File, the assembly is printed if none is given
+        #[clap(short, long)]
+        output: Option<String>,
+    },
+    /// Run a .asm program or a ROM image (.hack, .bin, .ihx, .rom) on the emulated Hack CPU
+    Run {
+        /// Input File
+        input_file: String,
+
+        /// Maximum number of instructions to execute
+        #[clap(short, long, default_value_t = 1_000_000)]
+        cycles: usize,
+
+        /// RAM to print after the program halted, either a single address or START..END
+        #[clap(long, value_parser = parse_ram_range)]
+        ram: Vec<Range<usize>>,
+
+        /// Set a RAM address before running the program, as ADDRESS=VALUE
+        #[clap(long, value_parser = parse_ram_value)]
+        set: Vec<(usize, i16)>,
+    },
+    /// Translate a .vm file or a directory of .vm files to Hack assembly
+    Vm2asm {
+        /// Input File or Directory
+        input: String,
+
+        /// Output File, defaults to the input with an .asm extension
+        #[clap(short, long)]
+        output: Option<String>,
+    },
+    /// Run a Nand2Tetris .tst test script on the emulated Hack CPU
+    Test {
+        /// Test Script
+        script: String,
+    },
+    /// Link object files created with --object into a single .hack file
+    Link {
+        /// Object Files, placed into the ROM in the given order
+        #[clap(required = true)]
+        input_files: Vec<String>,
+
+        /// Output File
+        #[clap(short, long)]
+        output: String,
+    },
+    /// Convert a ROM image from one format into another
+    Convert {
+        /// Input File, a .hack file, raw binary, Intel HEX or Logisim image
+        input_file: String,
+
+        /// Output File
+        output_file: String,
+
+        /// The format of the input, detected from its content if not given
+        #[clap(long, value_enum)]
+        from: Option<RomFormatArg>,
+
+        /// The format of the output, derived from the extension of the output file if not given
+        #[clap(long, value_enum)]
+        format: Option<OutputFormatArg>,
+
+        /// Pad ROM images to this many words
+        #[clap(long, value_name = "WORDS")]
+        rom_depth: Option<usize>,
+
+        /// Cut off the words of a program that does not fit into --rom-depth instead of failing
+        #[clap(long, requires = "rom_depth")]
+        truncate: bool,
+    },
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum RomFormatArg {
+    Hack,
+    Bin,
+    BinLe,
+    Ihex,
+    Logisim,
+}
+
+impl From<RomFormatArg> for hack_asm::RomFormat {
+    fn from(format: RomFormatArg) -> Self {
+        match format {
+            RomFormatArg::Hack => hack_asm::RomFormat::Hack,
+            RomFormatArg::Bin => hack_asm::RomFormat::Binary(hack_asm::Endianness::Big),
+            RomFormatArg::BinLe => hack_asm::RomFormat::Binary(hack_asm::Endianness::Little),
+            RomFormatArg::Ihex => hack_asm::RomFormat::IntelHex,
+            RomFormatArg::Logisim => hack_asm::RomFormat::Logisim,
+        }
+    }
+}
+
+fn parse_address(s: &str) -> Result<usize, String> {
+    let address = s
+        .trim()
+        .parse()
+        .map_err(|_| format!("\"{}\" is not an address", s))?;
+
+    if address >= hack_asm::Cpu::RAM_SIZE {
+        return Err(format!("address {} is outside of the RAM", address));
+    }
+
+    Ok(address)
+}
+
+fn parse_ram_range(s: &str) -> Result<Range<usize>, String> {
+    match s.split_once("..") {
+        Some((start, end)) => {
+            let start = parse_address(start)?;
+            let end = match end.trim() {
+                "" => hack_asm::Cpu::RAM_SIZE,
+                // the end is exclusive, so it may point right behind the RAM
+                end => parse_address(end).or_else(|e| match end.parse() {
+                    Ok(hack_asm::Cpu::RAM_SIZE) => Ok(hack_asm::Cpu::RAM_SIZE),
+                    _ => Err(e),
+                })?,
+            };
+            Ok(start..end)
+        }
+        None => parse_address(s).map(|address| address..address + 1),
+    }
+}
+
+fn parse_ram_value(s: &str) -> Result<(usize, i16), String> {
+    let (address, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected ADDRESS=VALUE, got \"{}\"", s))?;
+    let value = value
+        .trim()
+        .parse()
+        .map_err(|_| format!("\"{}\" is not a 16 bit value", value))?;
+
+    Ok((parse_address(address)?, value))
+}
 
-    let mut program = match read_to_string(&args.input_file) {
-        Ok(s) => s,
+fn read_input(path: &str) -> Option<String> {
+    match read_to_string(path) {
+        Ok(s) => Some(s),
         Err(e) => {
-            println!("error reading {}: {}", &args.input_file, e);
-            return;
+            eprintln!("error reading {}: {}", path, e);
+            None
         }
+    }
+}
+
+fn read_config(path: &str) -> Option<hack_asm::AssemblerOptions> {
+    match hack_asm::AssemblerOptions::from_config(&read_input(path)?) {
+        Ok(options) => Some(options),
+        Err(e) => {
+            eprintln!("error reading {}: {}", path, e);
+            None
+        }
+    }
+}
+
+fn assemble_with_diagnostics(
+    assembler: hack_asm::Assembler,
+    input_file: &str,
+) -> Option<hack_asm::AssembledProgram> {
+    let (result, diagnostics) = match assembler.assemble_with_diagnostics() {
+        Ok((output, diagnostics)) => (Some(output), diagnostics),
+        Err(diagnostics) => (None, diagnostics),
+    };
+
+    for diagnostic in &diagnostics {
+        eprintln!("{}\n", diagnostic);
+    }
+
+    let errors = diagnostics.iter().filter(|d| d.is_error()).count();
+    let warnings = diagnostics.len() - errors;
+    let plural = |n: usize| if n == 1 { "" } else { "s" };
+
+    if errors > 0 {
+        eprint!(
+            "error: could not assemble `{}` due to {} previous error{}",
+            input_file,
+            errors,
+            plural(errors)
+        );
+        if warnings > 0 {
+            eprint!("; {} warning{} emitted", warnings, plural(warnings));
+        }
+        eprintln!();
+    } else if warnings > 0 {
+        eprintln!("warning: {} warning{} emitted", warnings, plural(warnings));
+    }
+
+    result
+}
+
+fn assembler<'a>(
+    program: &'a str,
+    input_file: &str,
+    settings: &Settings,
+) -> hack_asm::Assembler<'a> {
+    let mut assembler = settings.include_dirs.iter().fold(
+        hack_asm::Assembler::new(program).with_file_name(input_file),
+        |assembler, dir| assembler.with_include_dir(dir),
+    );
+
+    if settings.no_pseudo {
+        assembler = assembler.without_pseudo_instructions();
+    }
+    assembler = assembler.with_options(settings.options.clone());
+    if settings.optimize {
+        assembler = assembler.with_optimizer(hack_asm::Optimizer::default());
+    }
+
+    assembler
+}
+
+fn assemble_file(
+    input_file: &str,
+    settings: &Settings,
+    diagnostics: bool,
+) -> Option<hack_asm::AssembledProgram> {
+    let mut program = read_input(input_file)?;
+
+    // so programs dont have to end with a newline
+    program.push('\n');
+
+    let assembler = assembler(&program, input_file, settings);
+    if diagnostics {
+        assemble_with_diagnostics(assembler, input_file)
+    } else {
+        match assembler.assemble_program() {
+            Ok(v) => Some(v),
+            Err(e) => {
+                eprintln!("{}", e);
+                None
+            }
+        }
+    }
+}
+
+fn assemble_object(input_file: &str, settings: &Settings) -> ExitCode {
+    let mut program = match read_input(input_file) {
+        Some(program) => program,
+        None => return ExitCode::FAILURE,
     };
 
     // so programs dont have to end with a newline
     program.push('\n');
 
-    let assembler = hack_asm::Assembler::new(&program);
-    let result = match assembler.assemble() {
-        Ok(v) => v,
+    let assembler = assembler(&program, input_file, settings);
+    let object = match assembler.assemble_object() {
+        Ok(object) => object,
         Err(e) => {
-            println!("{}", e);
-            return;
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
         }
     };
 
-    let mut outfile_path = PathBuf::from(args.input_file);
-    outfile_path.set_extension("hack");
+    let path = PathBuf::from(input_file).with_extension("o");
+    if write_file(&path, &object.to_string()) {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
 
-    let mut outfile = match std::fs::File::create(outfile_path) {
-        Ok(f) => f,
+fn write_file(path: &std::path::Path, content: &str) -> bool {
+    match std::fs::write(path, content) {
+        Ok(()) => true,
         Err(e) => {
-            println!("{}", e);
-            return;
+            eprintln!("error writing {}: {}", path.display(), e);
+            false
         }
+    }
+}
+
+fn write_program(
+    path: &std::path::Path,
+    format: &dyn hack_asm::OutputFormat,
+    program: &hack_asm::AssembledProgram,
+) -> ExitCode {
+    // written to memory first, so no file is left behind if the program does not fit the format
+    let mut output = Vec::new();
+    let result = format
+        .write(program, &mut output)
+        .and_then(|()| std::fs::write(path, output));
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error writing {}: {}", path.display(), e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn assemble(args: AssembleArgs) -> ExitCode {
+    let input_file = args.input_file.unwrap();
+    let mut options = match &args.config {
+        Some(config) => match read_config(config) {
+            Some(options) => options,
+            None => return ExitCode::FAILURE,
+        },
+        None => hack_asm::AssemblerOptions::new(),
     };
+    if args.strict {
+        options = options.with_dialect(hack_asm::Dialect::Strict);
+    }
+
+    let settings = Settings {
+        include_dirs: &args.include_dirs,
+        no_pseudo: args.no_pseudo,
+        options,
+        optimize: args.optimize,
+    };
+    if args.object {
+        return assemble_object(&input_file, &settings);
+    }
+
+    let program = match assemble_file(&input_file, &settings, args.diagnostics) {
+        Some(v) => v,
+        None => return ExitCode::FAILURE,
+    };
+
+    if let Some(report) = &program.optimization {
+        let rules: Vec<_> = report
+            .removed
+            .iter()
+            .filter(|(_, count)| *count > 0)
+            .map(|(rule, count)| format!("{}: {}", rule, count))
+            .collect();
+        let saved = report.words_saved();
+        print!(
+            "optimizer saved {} word{}",
+            saved,
+            if saved == 1 { "" } else { "s" }
+        );
+        if rules.is_empty() {
+            println!();
+        } else {
+            println!(" ({})", rules.join(", "));
+        }
+    }
 
-    for line in result {
-        match writeln!(outfile, "{:016b}", line) {
-            Ok(_) => (),
+    let input_path = PathBuf::from(&input_file);
+
+    if args.listing
+        && !write_file(
+            &input_path.with_extension("lst"),
+            &hack_asm::listing(&program),
+        )
+    {
+        return ExitCode::FAILURE;
+    }
+
+    let symbols = match args.symbols {
+        Some(SymbolFormat::Text) => Some(("sym", hack_asm::symbols_text(&program.symbols))),
+        Some(SymbolFormat::Json) => Some(("json", hack_asm::symbols_json(&program.symbols))),
+        None => None,
+    };
+    if let Some((extension, content)) = symbols {
+        if !write_file(&input_path.with_extension(extension), &content) {
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let format = args.format.format(depth(args.rom_depth, args.truncate));
+    write_program(
+        &input_path.with_extension(format.extension()),
+        format.as_ref(),
+        &program,
+    )
+}
+
+fn link(input_files: &[String], output: &str) -> ExitCode {
+    let mut modules = Vec::new();
+
+    for input_file in input_files {
+        let input = match read_input(input_file) {
+            Some(input) => input,
+            None => return ExitCode::FAILURE,
+        };
+
+        match hack_asm::ObjectFile::parse(&input) {
+            Ok(object) => modules.push((input_file.as_str(), object)),
             Err(e) => {
-                println!("{}", e);
-                return;
+                eprintln!("error: {}: {}", input_file, e);
+                return ExitCode::FAILURE;
             }
-        };
+        }
+    }
+
+    match hack_asm::link(&modules) {
+        Ok(words) => write_program(std::path::Path::new(output), &hack_asm::Hack, &words.into()),
+        Err(errors) => {
+            for error in errors {
+                eprintln!("error: {}", error);
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Reads a .hack file or any other ROM image [`hack_asm::HackRom`] can detect.
+fn read_hack_file(input_file: &str, format: Option<hack_asm::RomFormat>) -> Option<Vec<u16>> {
+    let input = match std::fs::read(input_file) {
+        Ok(input) => input,
+        Err(e) => {
+            eprintln!("error reading {}: {}", input_file, e);
+            return None;
+        }
+    };
+
+    let rom = match format {
+        Some(format) => hack_asm::HackRom::parse_as(&input, format),
+        None => hack_asm::HackRom::parse(&input),
+    };
+
+    match rom {
+        Ok(rom) => Some(rom.words),
+        Err(e) => {
+            eprintln!("error: {}: {}", input_file, e);
+            None
+        }
+    }
+}
+
+fn disasm(input_file: &str, output: Option<&str>) -> ExitCode {
+    let program = match read_hack_file(input_file, None) {
+        Some(p) => p,
+        None => return ExitCode::FAILURE,
+    };
+
+    let disassembly = hack_asm::disassemble(&program);
+
+    for (address, error) in &disassembly.errors {
+        eprintln!("warning: {}: address {}: {}", input_file, address, error);
+    }
+
+    match output {
+        Some(path) => {
+            if let Err(e) = std::fs::write(path, &disassembly.text) {
+                eprintln!("{}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+        None => print!("{}", disassembly.text),
+    }
+
+    if disassembly.errors.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+/// The size of ROM images, as given by --rom-depth and --truncate.
+fn depth(rom_depth: Option<usize>, truncate: bool) -> hack_asm::Depth {
+    match rom_depth {
+        Some(words) if truncate => hack_asm::Depth::Truncate(words),
+        Some(words) => hack_asm::Depth::Pad(words),
+        None => hack_asm::Depth::Program,
+    }
+}
+
+fn convert(
+    input_file: &str,
+    output_file: &str,
+    from: Option<RomFormatArg>,
+    format: Option<OutputFormatArg>,
+    depth: hack_asm::Depth,
+) -> ExitCode {
+    let output_path = std::path::Path::new(output_file);
+    let format = match format.or_else(|| OutputFormatArg::from_extension(output_path)) {
+        Some(format) => format.format(depth),
+        None => {
+            eprintln!(
+                "error: cannot tell the format of {} from its extension, pass --format",
+                output_file
+            );
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match read_hack_file(input_file, from.map(Into::into)) {
+        Some(words) => write_program(output_path, format.as_ref(), &words.into()),
+        None => ExitCode::FAILURE,
+    }
+}
+
+/// Loads a program either by assembling it or by reading an already assembled ROM image.
+fn load_program(input_file: &str) -> Option<Vec<u16>> {
+    let extension = std::path::Path::new(input_file).extension();
+    if matches!(
+        extension.and_then(|e| e.to_str()),
+        Some("hack" | "bin" | "ihx" | "rom")
+    ) {
+        read_hack_file(input_file, None)
+    } else {
+        assemble_file(input_file, &Settings::default(), false).map(|program| program.words)
+    }
+}
+
+fn run(input_file: &str, cycles: usize, ram: &[Range<usize>], set: &[(usize, i16)]) -> ExitCode {
+    let program = match load_program(input_file) {
+        Some(p) => p,
+        None => return ExitCode::FAILURE,
+    };
+
+    let mut cpu = match hack_asm::Cpu::new(&program) {
+        Ok(cpu) => cpu,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    for &(address, value) in set {
+        cpu.ram_mut()[address] = value as u16;
+    }
+
+    let executed = cpu.run(cycles);
+    if cpu.is_halted() {
+        eprintln!("halted after {} cycles", executed);
+    } else {
+        eprintln!("stopped after {} cycles at pc {}", executed, cpu.pc());
+    }
+
+    for range in ram {
+        for address in range.clone() {
+            println!("RAM[{}] = {}", address, cpu.ram()[address] as i16);
+        }
+    }
+
+    ExitCode::SUCCESS
+}
+
+fn test(script: &str) -> ExitCode {
+    let result = hack_asm::run_test_script(std::path::Path::new(script));
+
+    let report = match result {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("error: {}: {}", script, e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    for echo in &report.echoes {
+        println!("{}", echo);
+    }
+
+    if report.compared {
+        println!("End of script - Comparison ended successfully");
+    } else {
+        println!("End of script");
+    }
+
+    ExitCode::SUCCESS
+}
+
+fn vm2asm(input: &str, output: Option<&str>) -> ExitCode {
+    let input = PathBuf::from(input);
+
+    let asm = match hack_asm::vm::translate_path(&input) {
+        Ok(asm) => asm,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let output = match output {
+        Some(output) => PathBuf::from(output),
+        // a directory Foo is translated into Foo/Foo.asm
+        None if input.is_dir() => {
+            let name = input.file_name().unwrap_or_default().to_os_string();
+            input.join(name).with_extension("asm")
+        }
+        None => input.with_extension("asm"),
+    };
+
+    if let Err(e) = std::fs::write(&output, asm) {
+        eprintln!("error writing {}: {}", output.display(), e);
+        return ExitCode::FAILURE;
+    }
+
+    ExitCode::SUCCESS
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+
+    match args.command {
+        Some(Command::Disasm { input_file, output }) => disasm(&input_file, output.as_deref()),
+        Some(Command::Run {
+            input_file,
+            cycles,
+            ram,
+            set,
+        }) => run(&input_file, cycles, &ram, &set),
+        Some(Command::Vm2asm { input, output }) => vm2asm(&input, output.as_deref()),
+        Some(Command::Test { script }) => test(&script),
+        Some(Command::Link {
+            input_files,
+            output,
+        }) => link(&input_files, &output),
+        Some(Command::Convert {
+            input_file,
+            output_file,
+            from,
+            format,
+            rom_depth,
+            truncate,
+        }) => convert(
+            &input_file,
+            &output_file,
+            from,
+            format,
+            depth(rom_depth, truncate),
+        ),
+        None => assemble(args.assemble),
     }
 }
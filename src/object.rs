@@ -0,0 +1,380 @@
+use std::{collections::HashMap, fmt};
+
+use thiserror::Error;
+
+use crate::{
+    assembler_context::{AssemblerContext, AssemblerError, AssemblerErrorKind},
+    parsing::parse_reference,
+    symbol_table::{Symbol, SymbolKind, SymbolTableGetError},
+};
+
+const HEADER: &str = "hackobj 1";
+
+/// A word of an object file, either already assembled or left for the linker.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ObjectWord {
+    Word(u16),
+    /// An a-instruction such as `@LOOP + 1` that refers to a label or to a symbol
+    /// which is not defined by the module, it is resolved once the program is linked.
+    Reference(String),
+}
+
+/// A separately assembled module, see [`crate::Assembler::assemble_object`].
+///
+/// Labels are stored relative to the start of the module and are moved to their final
+/// address by [`link`]. Symbols that are not defined by the module either are variables
+/// the module requests, which are shared by every module, or refer to a label exported
+/// by another module.
+///
+/// Object files are stored as text, one entry per line:
+/// ```text
+/// hackobj 1
+/// label LOOP 2
+/// constant ROWS 5
+/// export LOOP
+/// variable counter
+/// word 0000000000000101
+/// ref @counter
+/// ref @LOOP + 1
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ObjectFile {
+    pub code: Vec<ObjectWord>,
+    /// Every label and constant of the module.
+    pub symbols: Vec<Symbol>,
+    /// The labels other modules may refer to.
+    pub exports: Vec<String>,
+    /// The symbols the module uses as variables, every other symbol it does not define
+    /// has to be exported by another module.
+    pub variables: Vec<String>,
+}
+
+#[derive(Error, Debug)]
+pub enum ObjectError {
+    #[error("not an object file, expected \"{HEADER}\" in the first line")]
+    MissingHeader,
+    #[error("line {line}: invalid entry \"{text}\"")]
+    InvalidLine { line: usize, text: String },
+    #[error("exported symbol \"{0}\" is not a label of the module")]
+    UnknownExport(String),
+}
+
+#[derive(Error, Debug)]
+pub enum LinkError {
+    #[error("{module}: {error}")]
+    Assembler {
+        module: String,
+        error: AssemblerErrorKind,
+    },
+    #[error("{module}: {reference}: {error}")]
+    Reference {
+        module: String,
+        reference: String,
+        error: AssemblerErrorKind,
+    },
+    #[error("label \"{name}\" is exported by both {first} and {second}")]
+    DuplicateExport {
+        name: String,
+        first: String,
+        second: String,
+    },
+    #[error("{module}: symbol \"{name}\" is neither defined in the module nor exported by any other module")]
+    MissingExport { module: String, name: String },
+}
+
+impl ObjectFile {
+    /// Reads an object file in the format written by its [`fmt::Display`] implementation.
+    pub fn parse(input: &str) -> Result<Self, ObjectError> {
+        let mut lines = input.lines().enumerate();
+        let mut object = ObjectFile::default();
+
+        if lines.next().map(|(_, line)| line.trim()) != Some(HEADER) {
+            return Err(ObjectError::MissingHeader);
+        }
+
+        for (index, text) in lines {
+            let invalid = || ObjectError::InvalidLine {
+                line: index + 1,
+                text: text.to_string(),
+            };
+            let symbol = |kind, rest: &str| {
+                let (name, value) = rest.split_once(' ').ok_or_else(invalid)?;
+                let value = value.parse().map_err(|_| invalid())?;
+
+                Ok(Symbol {
+                    name: name.to_string(),
+                    value,
+                    kind,
+                })
+            };
+
+            match text.trim().split_once(' ') {
+                Some(("label", rest)) => object.symbols.push(symbol(SymbolKind::Label, rest)?),
+                Some(("constant", rest)) => {
+                    object.symbols.push(symbol(SymbolKind::Constant, rest)?)
+                }
+                Some(("export", name)) => object.exports.push(name.to_string()),
+                Some(("variable", name)) => object.variables.push(name.to_string()),
+                Some(("word", bits)) if bits.len() == 16 => {
+                    let word = u16::from_str_radix(bits, 2).map_err(|_| invalid())?;
+                    object.code.push(ObjectWord::Word(word));
+                }
+                Some(("ref", reference)) => object
+                    .code
+                    .push(ObjectWord::Reference(reference.to_string())),
+                None if text.trim().is_empty() => (),
+                _ => return Err(invalid()),
+            }
+        }
+
+        for export in &object.exports {
+            if object.label(export).is_none() {
+                return Err(ObjectError::UnknownExport(export.clone()));
+            }
+        }
+
+        Ok(object)
+    }
+
+    fn label(&self, name: &str) -> Option<u16> {
+        self.symbols
+            .iter()
+            .find(|s| s.kind == SymbolKind::Label && s.name == name)
+            .map(|s| s.value)
+    }
+}
+
+impl fmt::Display for ObjectFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+
+        for symbol in &self.symbols {
+            writeln!(f, "{} {} {}", symbol.kind, symbol.name, symbol.value)?;
+        }
+
+        for export in &self.exports {
+            writeln!(f, "export {}", export)?;
+        }
+
+        for variable in &self.variables {
+            writeln!(f, "variable {}", variable)?;
+        }
+
+        for word in &self.code {
+            match word {
+                ObjectWord::Word(word) => writeln!(f, "word {:016b}", word)?,
+                ObjectWord::Reference(reference) => writeln!(f, "ref {}", reference)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Links separately assembled modules into one program.
+///
+/// The modules are placed into the ROM in the given order. A symbol a module does not define
+/// itself is a variable if the module requests one, otherwise it resolves to the label another
+/// module exports under that name. Variables are shared by every module and allocated from
+/// address 16. Returns every error that was found, each one names the module it belongs to.
+pub fn link(modules: &[(&str, ObjectFile)]) -> Result<Vec<u16>, Vec<LinkError>> {
+    let mut errors = Vec::new();
+
+    if modules.iter().map(|(_, o)| o.code.len()).sum::<usize>() > crate::constants::ROM_SIZE {
+        errors.push(LinkError::Assembler {
+            module: modules.last().map_or("", |(m, _)| m).to_string(),
+            error: AssemblerErrorKind::TooManyInstructions,
+        });
+        return Err(errors);
+    }
+
+    let mut exports: HashMap<&str, (&str, u16)> = HashMap::new();
+    let mut base = 0;
+
+    for (module, object) in modules {
+        for name in &object.exports {
+            // the objects are checked when they are parsed, but may have been built by hand
+            let Some(address) = object.label(name) else {
+                errors.push(LinkError::Assembler {
+                    module: module.to_string(),
+                    error: AssemblerErrorKind::ExportNotLabel(name.clone()),
+                });
+                continue;
+            };
+
+            match exports.get(name.as_str()) {
+                Some((first, _)) => errors.push(LinkError::DuplicateExport {
+                    name: name.clone(),
+                    first: first.to_string(),
+                    second: module.to_string(),
+                }),
+                None => {
+                    exports.insert(name, (module, base + address));
+                }
+            }
+        }
+
+        base += object.code.len() as u16;
+    }
+
+    let mut words = Vec::new();
+    let mut variables: Vec<Symbol> = Vec::new();
+
+    for (module, object) in modules {
+        let base = words.len() as u16;
+        let assembler_error = |error: AssemblerError| LinkError::Assembler {
+            module: module.to_string(),
+            error: error.kind,
+        };
+
+        // symbols of the module hide its variables, which hide the exports of other modules
+        let mut symbols: Vec<Symbol> = object
+            .symbols
+            .iter()
+            .map(|symbol| match symbol.kind {
+                SymbolKind::Label => Symbol {
+                    value: base + symbol.value,
+                    ..symbol.clone()
+                },
+                _ => symbol.clone(),
+            })
+            .collect();
+        let hidden = |symbols: &[Symbol], name: &str| symbols.iter().any(|s| s.name == name);
+
+        for variable in &variables {
+            if object.variables.contains(&variable.name) && !hidden(&symbols, &variable.name) {
+                symbols.push(variable.clone());
+            }
+        }
+        for (&name, &(_, address)) in &exports {
+            if !object.variables.iter().any(|v| v == name) && !hidden(&symbols, name) {
+                symbols.push(Symbol {
+                    name: name.to_string(),
+                    value: address,
+                    kind: SymbolKind::Label,
+                });
+            }
+        }
+
+        let mut context = match AssemblerContext::with_symbols(&symbols) {
+            Ok(context) => context,
+            Err(e) => {
+                errors.push(assembler_error(e));
+                words.resize(base as usize + object.code.len(), 0);
+                continue;
+            }
+        };
+
+        // the variables of the module that no module before it has requested
+        for name in &object.variables {
+            if let Err(e) = context.get_or_create_variable(name) {
+                errors.push(assembler_error(e));
+            }
+        }
+
+        for word in &object.code {
+            let reference = match word {
+                ObjectWord::Word(word) => {
+                    words.push(*word);
+                    continue;
+                }
+                ObjectWord::Reference(reference) => reference,
+            };
+
+            // every symbol is defined by now, unless it is missing from the exports
+            let resolved = parse_reference(reference).and_then(|i| {
+                for name in i.value.symbols() {
+                    context.get_defined_symbol(name)?;
+                }
+                i.resolve(&mut context)
+            });
+            words.push(*resolved.as_ref().unwrap_or(&0));
+
+            match resolved.map_err(|e| e.kind) {
+                Ok(_) => (),
+                Err(AssemblerErrorKind::SymbolTableGetError(SymbolTableGetError::NotDefined(
+                    name,
+                ))) => errors.push(LinkError::MissingExport {
+                    module: module.to_string(),
+                    name,
+                }),
+                Err(error) => errors.push(LinkError::Reference {
+                    module: module.to_string(),
+                    reference: reference.clone(),
+                    error,
+                }),
+            }
+        }
+
+        for symbol in context.symbols() {
+            if symbol.kind == SymbolKind::Variable && !hidden(&variables, &symbol.name) {
+                variables.push(symbol);
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(words)
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Assembler;
+
+    fn object(program: &str) -> ObjectFile {
+        let object = Assembler::new(program).assemble_object().unwrap();
+        ObjectFile::parse(&object.to_string()).unwrap()
+    }
+
+    #[test]
+    fn test_link_modules() {
+        let main =
+            object(".export END\n.import INC\n@counter\nM=0\n@INC\n0;JMP\n(END)\n@END\n0;JMP\n");
+        let inc =
+            object(".export INC\n.import END\n(INC)\n@counter\nM=M+1\n@other\n@INC+1\n@END\n");
+        assert_eq!(main.variables, ["counter"]);
+        assert_eq!(inc.variables, ["counter", "other"]);
+
+        let words = link(&[("main.o", main), ("inc.o", inc)]).unwrap();
+        assert_eq!(words, [16, 60040, 6, 60039, 4, 60039, 16, 64968, 17, 7, 4]);
+    }
+
+    #[test]
+    fn test_link_duplicate_export() {
+        let main = object(".export END\n(END)\n@END\n0;JMP\n");
+        let errors = link(&[("main.o", main.clone()), ("main2.o", main)]).unwrap_err();
+        assert!(matches!(&errors[..], [LinkError::DuplicateExport { .. }]));
+    }
+
+    #[test]
+    fn test_link_missing_export() {
+        let errors = link(&[("end.o", object(".import END\n@END\n"))]).unwrap_err();
+        assert!(matches!(&errors[..], [LinkError::MissingExport { name, .. }] if name == "END"));
+
+        let errors = link(&[("end.o", object("@END+1\n"))]).unwrap_err();
+        assert!(matches!(&errors[..], [LinkError::MissingExport { name, .. }] if name == "END"));
+    }
+
+    #[test]
+    fn test_variable_is_not_an_export() {
+        // a module that does not import END gets a variable, even though another module exports it
+        let main = object(".export END\n(END)\n@END\n");
+        let words = link(&[("main.o", main), ("var.o", object("@END\n"))]).unwrap();
+        assert_eq!(words, [0, 16]);
+    }
+
+    #[test]
+    fn test_import_in_a_whole_program() {
+        let error = Assembler::new(".import END\n@END\n")
+            .assemble()
+            .unwrap_err();
+        assert!(matches!(error.kind, AssemblerErrorKind::MissingImport(name) if name == "END"));
+        assert!(Assembler::new(".import END\n(END)\n@END\n")
+            .assemble()
+            .is_ok());
+    }
+}
@@ -0,0 +1,363 @@
+use std::ops::Range;
+
+use crate::{
+    instructions::{CInstruction, Computation, JumpType, Label, Register},
+    parsing::ParsedInstruction,
+};
+
+/// A rule of the [`Optimizer`] that removes instructions which do not change what the program does.
+pub trait PeepholeRule {
+    /// The name the rule is reported under.
+    fn name(&self) -> &'static str;
+
+    /// Looks at the instructions starting at the current one and returns the range of them
+    /// that can be removed, relative to the start of `window`.
+    ///
+    /// The window ends in front of the next label, so only its first instruction can be
+    /// the target of a jump. `is_defined` tells whether a symbol is a label, a constant or
+    /// a built in symbol, rather than a variable that is created where it is first used.
+    fn apply(
+        &self,
+        window: &[ParsedInstruction],
+        is_defined: &dyn Fn(&str) -> bool,
+    ) -> Option<Range<usize>>;
+}
+
+/// Removes a repeated `@X` and c-instruction pair such as `@SP A=M @SP A=M`,
+/// as long as the first pair leaves everything the second one reads untouched.
+pub struct RedundantReload;
+
+/// Removes an a-instruction whose value is replaced by the next one, like the first of `@1 @2`.
+/// Only literals, labels and constants are removed, since removing the first use of a variable
+/// would move every variable after it and could hide an undefined symbol in an expression.
+pub struct OverwrittenLoad;
+
+/// Removes c-instructions that neither change a register nor jump, such as `D=D`.
+pub struct NoOp;
+
+/// Removes the instructions after an unconditional jump that no label points at.
+pub struct UnreachableCode;
+
+fn c_instruction<'a>(instruction: &'a ParsedInstruction) -> Option<&'a CInstruction> {
+    match instruction {
+        ParsedInstruction::CInstruction(instruction, _) => Some(instruction),
+        ParsedInstruction::AInstruction(..) => None,
+    }
+}
+
+fn reads(computation: Computation, register: Register) -> bool {
+    match computation {
+        Computation::Literal(_) => false,
+        Computation::Identity(r)
+        | Computation::Not(r)
+        | Computation::Neg(r)
+        | Computation::Inc(r)
+        | Computation::Dec(r) => r == register,
+        Computation::Add(a, b)
+        | Computation::Sub(a, b)
+        | Computation::And(a, b)
+        | Computation::Or(a, b) => a == register || b == register,
+    }
+}
+
+fn writes(instruction: &CInstruction, register: Register) -> bool {
+    instruction
+        .destination
+        .as_ref()
+        .is_some_and(|destination| destination.contains(&register))
+}
+
+impl PeepholeRule for RedundantReload {
+    fn name(&self) -> &'static str {
+        "redundant-reload"
+    }
+
+    fn apply(
+        &self,
+        window: &[ParsedInstruction],
+        _: &dyn Fn(&str) -> bool,
+    ) -> Option<Range<usize>> {
+        let [ParsedInstruction::AInstruction(first, _), c, ParsedInstruction::AInstruction(second, _), repeated, ..] =
+            window
+        else {
+            return None;
+        };
+        let (c, repeated) = (c_instruction(c)?, c_instruction(repeated)?);
+
+        // the second @X restores A, so only a changed D or M could make the result differ
+        let idempotent = c.jump.is_none()
+            && !writes(c, Register::M)
+            && !(writes(c, Register::D) && reads(c.computation, Register::D));
+
+        (idempotent && first.value.to_string() == second.value.to_string() && c == repeated)
+            .then_some(2..4)
+    }
+}
+
+impl PeepholeRule for OverwrittenLoad {
+    fn name(&self) -> &'static str {
+        "overwritten-load"
+    }
+
+    fn apply(
+        &self,
+        window: &[ParsedInstruction],
+        is_defined: &dyn Fn(&str) -> bool,
+    ) -> Option<Range<usize>> {
+        let [ParsedInstruction::AInstruction(first, _), ParsedInstruction::AInstruction(..), ..] =
+            window
+        else {
+            return None;
+        };
+
+        first
+            .value
+            .symbols()
+            .into_iter()
+            .all(is_defined)
+            .then_some(0..1)
+    }
+}
+
+impl PeepholeRule for NoOp {
+    fn name(&self) -> &'static str {
+        "no-op"
+    }
+
+    fn apply(
+        &self,
+        window: &[ParsedInstruction],
+        _: &dyn Fn(&str) -> bool,
+    ) -> Option<Range<usize>> {
+        let c = c_instruction(window.first()?)?;
+        let unchanged = match (c.destination.as_deref(), c.computation) {
+            (None, _) => true,
+            (Some(&[register]), Computation::Identity(identity)) => register == identity,
+            _ => false,
+        };
+
+        (unchanged && c.jump.is_none()).then_some(0..1)
+    }
+}
+
+impl PeepholeRule for UnreachableCode {
+    fn name(&self) -> &'static str {
+        "unreachable-code"
+    }
+
+    fn apply(
+        &self,
+        window: &[ParsedInstruction],
+        _: &dyn Fn(&str) -> bool,
+    ) -> Option<Range<usize>> {
+        let c = c_instruction(window.first()?)?;
+
+        (matches!(c.jump, Some(JumpType::Jmp)) && window.len() > 1).then_some(1..window.len())
+    }
+}
+
+/// How many words every rule of the [`Optimizer`] removed.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OptimizationReport {
+    /// The name of every rule together with the number of words it removed.
+    pub removed: Vec<(&'static str, usize)>,
+}
+
+impl OptimizationReport {
+    /// The number of words the program got shorter.
+    pub fn words_saved(&self) -> usize {
+        self.removed.iter().map(|(_, count)| count).sum()
+    }
+}
+
+/// A peephole optimizer that runs over the parsed instructions before they are assembled.
+///
+/// Labels are moved to the address of the instruction they pointed at, so jumps and
+/// expressions using labels stay correct. Jumps to numeric addresses such as `@12` are not
+/// adjusted, neither are constants holding an address.
+pub struct Optimizer {
+    rules: Vec<Box<dyn PeepholeRule>>,
+}
+
+impl Default for Optimizer {
+    /// An optimizer using every built-in rule.
+    fn default() -> Self {
+        Self::new()
+            .with_rule(UnreachableCode)
+            .with_rule(NoOp)
+            .with_rule(OverwrittenLoad)
+            .with_rule(RedundantReload)
+    }
+}
+
+impl Optimizer {
+    /// An optimizer without any rules, see [`Optimizer::with_rule`].
+    pub fn new() -> Self {
+        Self { rules: Vec::new() }
+    }
+
+    /// Adds a rule, rules are tried in the order they were added.
+    pub fn with_rule(mut self, rule: impl PeepholeRule + 'static) -> Self {
+        self.rules.push(Box::new(rule));
+        self
+    }
+
+    /// Applies the rules until none of them removes another instruction
+    /// and moves the labels accordingly.
+    pub(crate) fn optimize(
+        &self,
+        instructions: &mut Vec<ParsedInstruction>,
+        labels: &mut [(Label, usize)],
+        is_defined: &dyn Fn(&str) -> bool,
+    ) -> OptimizationReport {
+        let mut removed = vec![0; self.rules.len()];
+        // the index of every instruction a label points at, in ascending order
+        let mut targets: Vec<usize> = labels.iter().map(|(_, index)| *index).collect();
+        targets.sort_unstable();
+        targets.dedup();
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            let mut index = 0;
+
+            while index < instructions.len() {
+                let end = targets[targets.partition_point(|&t| t <= index)..]
+                    .first()
+                    .map_or(instructions.len(), |&t| t.min(instructions.len()));
+                let window = &instructions[index..end];
+
+                let applied = self.rules.iter().enumerate().find_map(|(rule, r)| {
+                    r.apply(window, is_defined)
+                        .filter(|range| !range.is_empty() && range.end <= window.len())
+                        .map(|range| (rule, index + range.start..index + range.end))
+                });
+
+                let Some((rule, range)) = applied else {
+                    index += 1;
+                    continue;
+                };
+
+                let count = range.len();
+                let shift = |target: &mut usize| {
+                    if *target >= range.end {
+                        *target -= count;
+                    }
+                };
+                labels.iter_mut().for_each(|(_, target)| shift(target));
+                targets.iter_mut().for_each(shift);
+                instructions.drain(range);
+
+                removed[rule] += count;
+                changed = true;
+            }
+        }
+
+        OptimizationReport {
+            removed: self
+                .rules
+                .iter()
+                .zip(removed)
+                .map(|(rule, count)| (rule.name(), count))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Assembler;
+
+    use super::*;
+
+    fn optimize_with(rule: impl PeepholeRule + 'static, program: &str) -> Vec<u16> {
+        Assembler::new(program)
+            .with_optimizer(Optimizer::new().with_rule(rule))
+            .assemble()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_redundant_reload() {
+        // @SP A=M D=M
+        assert_eq!(
+            optimize_with(RedundantReload, "@SP\nA=M\n@SP\nA=M\nD=M\n"),
+            [0, 0xFC20, 0xFC10]
+        );
+    }
+
+    #[test]
+    fn test_redundant_reload_after_a_label() {
+        // a label between the pairs may be reached with another value in A
+        let program = "@SP\nA=M\n(L)\n@SP\nA=M\n@L\n";
+        assert_eq!(optimize_with(RedundantReload, program).len(), 5);
+    }
+
+    #[test]
+    fn test_overwritten_load() {
+        assert_eq!(optimize_with(OverwrittenLoad, "@1\n@2\nD=A\n"), [2, 0xEC10]);
+    }
+
+    #[test]
+    fn test_no_op() {
+        assert_eq!(optimize_with(NoOp, "D=D\nD=A\n"), [0xEC10]);
+    }
+
+    #[test]
+    fn test_unreachable_code() {
+        let program = "(LOOP)\n@LOOP\n0;JMP\nD=M\n@2\n(END)\n@END\n0;JMP\n";
+        assert_eq!(
+            optimize_with(UnreachableCode, program),
+            [0, 0xEA87, 2, 0xEA87]
+        );
+    }
+
+    #[test]
+    fn test_report() {
+        let program =
+            "@SP\nA=M\n@SP\nA=M\nD=D\n@1\n@2\nD=A\n(LOOP)\n@LOOP\n0;JMP\nD=M\n@2\n(END)\n@END\n0;JMP\n";
+        let optimized = Assembler::new(program)
+            .with_optimizer(Optimizer::default())
+            .assemble_program()
+            .unwrap();
+
+        let report = optimized.optimization.unwrap();
+        assert_eq!(report.words_saved(), 6);
+        assert_eq!(
+            report.removed,
+            [
+                ("unreachable-code", 2),
+                ("no-op", 1),
+                ("overwritten-load", 1),
+                ("redundant-reload", 2)
+            ]
+        );
+    }
+
+    fn optimize(program: &str) -> Result<Vec<u16>, crate::AssemblerError> {
+        Assembler::new(program)
+            .with_optimizer(Optimizer::new().with_rule(OverwrittenLoad))
+            .assemble()
+    }
+
+    #[test]
+    fn test_overwritten_labels_and_constants() {
+        let program = ".equ ROWS 5\n(START)\n@START\n@ROWS\n@SCREEN\n@1\nD=A\n";
+        assert_eq!(optimize(program).unwrap(), [1, 0xEC10]);
+    }
+
+    #[test]
+    fn test_first_use_of_a_variable_is_kept() {
+        // removing @a would move b to address 16
+        assert_eq!(optimize("@a\n@b\nD=A\n@b\n").unwrap(), [16, 17, 0xEC10, 17]);
+    }
+
+    #[test]
+    fn test_undefined_symbol_is_still_reported() {
+        let error = optimize("@UNDEFINED+1\n@0\n").unwrap_err();
+        assert!(matches!(
+            error.kind,
+            crate::AssemblerErrorKind::SymbolTableGetError(_)
+        ));
+    }
+}
@@ -0,0 +1,347 @@
+use std::{collections::HashMap, ops::Range};
+
+use thiserror::Error;
+
+use crate::hack_int::HackInt;
+
+#[derive(Error, Debug)]
+pub enum OptionsError {
+    #[error(
+        "variables have to be placed between address 0 and {}, not at {start}..{end}",
+        HackInt::MAX
+    )]
+    InvalidVariableRange { start: u16, end: u16 },
+    #[error(
+        "the ROM has to hold between 1 and {} instructions, not {0}",
+        crate::constants::ROM_SIZE
+    )]
+    InvalidRomSize(usize),
+    #[error(
+        "the value {value} of symbol \"{name}\" is not between 0 and {}",
+        HackInt::MAX
+    )]
+    InvalidSymbolValue { name: String, value: u16 },
+    #[error("line {line}: {reason}")]
+    InvalidConfig { line: usize, reason: String },
+}
+
+/// How closely a program has to follow the spelling of the Hack specification.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Dialect {
+    /// Only the canonical spellings are accepted: destinations such as `MD` or `AMD`,
+    /// computations such as `D+A` rather than `A+D`, uppercase registers
+    /// and uppercase jump mnemonics. Meant for grading assignments.
+    Strict,
+    /// Destinations may be written in any order and case, `dm=m+1` is the same as `MD=M+1`.
+    /// A register that is named twice, like in `DD=`, is still an error.
+    #[default]
+    Permissive,
+}
+
+/// Settings that change how a program is assembled, see [`crate::Assembler::with_options`].
+///
+/// Besides the dialect, the options describe the machine the program runs on:
+/// where variables are placed, how many instructions fit into the ROM and which
+/// symbols are predefined.
+///
+/// ```
+/// # use hack_asm::{Assembler, AssemblerOptions};
+/// let options = AssemblerOptions::new()
+///     .with_variables(1024..2048)
+///     .with_predefined_symbol("LED", 0x6001)
+///     .with_symbol("BLINK_DELAY", 500);
+///
+/// let program = "@BLINK_DELAY\nD=A\n@LED\nM=D\n@counter\nM=D";
+/// let words = Assembler::new(program).with_options(options).assemble().unwrap();
+/// assert_eq!(words, [500, 0xEC10, 0x6001, 0xE308, 1024, 0xE308]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct AssemblerOptions {
+    pub(crate) dialect: Dialect,
+    pub(crate) variables: Range<u16>,
+    pub(crate) rom_size: usize,
+    /// Changes to the built in symbols, `None` removes a symbol.
+    pub(crate) predefined: HashMap<String, Option<u16>>,
+    pub(crate) symbols: Vec<(String, u16)>,
+}
+
+impl AssemblerOptions {
+    /// The default options, which accept the permissive dialect
+    /// and describe the machine of the Hack specification.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_dialect(mut self, dialect: Dialect) -> Self {
+        self.dialect = dialect;
+        self
+    }
+
+    /// Sets the addresses new variables are placed at, 16 up to the screen by default.
+    /// Running out of them is an error.
+    pub fn with_variables(mut self, addresses: Range<u16>) -> Self {
+        self.variables = addresses;
+        self
+    }
+
+    /// Sets how many instructions fit into the ROM, 32768 by default.
+    pub fn with_rom_size(mut self, size: usize) -> Self {
+        self.rom_size = size;
+        self
+    }
+
+    /// Adds a symbol that is defined like `SCREEN` or `R0`, such as an additional
+    /// memory mapped device. Programs can use, but not redefine it.
+    /// A built in symbol of the same name gets the new value.
+    pub fn with_predefined_symbol(mut self, name: &str, value: u16) -> Self {
+        self.predefined.insert(name.to_string(), Some(value));
+        self
+    }
+
+    /// Removes a predefined symbol, so the program can define it itself.
+    pub fn without_predefined_symbol(mut self, name: &str) -> Self {
+        self.predefined.insert(name.to_string(), None);
+        self
+    }
+
+    /// Defines a constant outside of the program, as if it had been defined by a `.define`
+    /// in front of it. It is listed among the symbols of the program.
+    pub fn with_symbol(mut self, name: &str, value: u16) -> Self {
+        self.symbols.push((name.to_string(), value));
+        self
+    }
+
+    /// Reads options from a configuration file, one setting per line:
+    ///
+    /// ```text
+    /// # the memory of the FPGA board
+    /// variables = 16..4096
+    /// rom_size = 8192
+    /// dialect = strict
+    /// predefined LED = 0x6001
+    /// remove R15
+    /// symbol BAUD = 9600
+    /// ```
+    ///
+    /// Everything after a `#` is a comment. Settings that are missing keep their default.
+    pub fn from_config(input: &str) -> Result<Self, OptionsError> {
+        let mut options = Self::default();
+
+        for (index, line) in input.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let invalid = |reason: String| OptionsError::InvalidConfig {
+                line: index + 1,
+                reason,
+            };
+            let value = |text: &str| {
+                HackInt::parse(text.trim())
+                    .map(u16::from)
+                    .map_err(|e| invalid(e.to_string()))
+            };
+
+            if let Some(name) = line.strip_prefix("remove ") {
+                options = options.without_predefined_symbol(name.trim());
+                continue;
+            }
+
+            let (key, setting) = line
+                .split_once('=')
+                .ok_or_else(|| invalid(format!("expected \"key = value\", found \"{}\"", line)))?;
+            let setting = setting.trim();
+
+            match key.split_whitespace().collect::<Vec<_>>()[..] {
+                ["variables"] => {
+                    let (start, end) = setting
+                        .split_once("..")
+                        .ok_or_else(|| invalid("expected variables = START..END".to_string()))?;
+                    let address = |text: &str| {
+                        HackInt::parse_word(text.trim()).map_err(|e| invalid(e.to_string()))
+                    };
+                    options = options.with_variables(address(start)?..address(end)?);
+                }
+                ["rom_size"] => {
+                    let size = setting.parse().map_err(|_| {
+                        invalid(format!("\"{}\" is not a number of instructions", setting))
+                    })?;
+                    options = options.with_rom_size(size);
+                }
+                ["dialect"] => {
+                    let dialect = match setting {
+                        "strict" => Dialect::Strict,
+                        "permissive" => Dialect::Permissive,
+                        _ => return Err(invalid(format!("unknown dialect \"{}\"", setting))),
+                    };
+                    options = options.with_dialect(dialect);
+                }
+                ["predefined", name] => {
+                    options = options.with_predefined_symbol(name, value(setting)?);
+                }
+                ["symbol", name] => options = options.with_symbol(name, value(setting)?),
+                _ => return Err(invalid(format!("unknown setting \"{}\"", key.trim()))),
+            }
+        }
+
+        Ok(options)
+    }
+
+    /// Checks the settings that cannot be checked while they are set.
+    pub(crate) fn validate(&self) -> Result<(), OptionsError> {
+        let Range { start, end } = self.variables;
+        if start > end || end > HackInt::MAX + 1 {
+            return Err(OptionsError::InvalidVariableRange { start, end });
+        }
+
+        if self.rom_size == 0 || self.rom_size > crate::constants::ROM_SIZE {
+            return Err(OptionsError::InvalidRomSize(self.rom_size));
+        }
+
+        let predefined = self
+            .predefined
+            .iter()
+            .filter_map(|(name, value)| value.map(|value| (name, value)));
+        let symbols = self.symbols.iter().map(|(name, value)| (name, *value));
+
+        for (name, value) in predefined.chain(symbols) {
+            if value > HackInt::MAX {
+                return Err(OptionsError::InvalidSymbolValue {
+                    name: name.clone(),
+                    value,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for AssemblerOptions {
+    fn default() -> Self {
+        Self {
+            dialect: Dialect::default(),
+            variables: crate::constants::VARIABLES,
+            rom_size: crate::constants::ROM_SIZE,
+            predefined: HashMap::new(),
+            symbols: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Assembler, AssemblerErrorKind};
+
+    const CONFIG: &str = "
+        # two variables above the stack
+        variables = 256..258
+        rom_size = 8
+        predefined LED = 0x6001
+        remove R15
+        symbol DELAY = 500
+    ";
+
+    fn assemble(program: &str, options: AssemblerOptions) -> Result<Vec<u16>, AssemblerErrorKind> {
+        Assembler::new(program)
+            .with_options(options)
+            .assemble()
+            .map_err(|e| e.kind)
+    }
+
+    #[test]
+    fn test_config() {
+        let options = AssemblerOptions::from_config(CONFIG).unwrap();
+        let program = Assembler::new("@a\n@b\n@LED\n@DELAY\n(R15)\n@R15\n")
+            .with_options(options)
+            .assemble_program()
+            .unwrap();
+        assert_eq!(program.words, [256, 257, 0x6001, 500, 4]);
+        assert!(program.symbols.iter().any(|s| s.name == "DELAY"));
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        assert!(matches!(
+            AssemblerOptions::from_config("symbol X = 40000"),
+            Err(OptionsError::InvalidConfig { line: 1, .. })
+        ));
+        assert!(matches!(
+            AssemblerOptions::from_config("\nrom_size: 8"),
+            Err(OptionsError::InvalidConfig { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn test_variables_run_out() {
+        let options = AssemblerOptions::new().with_variables(256..258);
+        assert!(matches!(
+            assemble("@a\n@b\n@c\n", options),
+            Err(AssemblerErrorKind::TooManyVariables)
+        ));
+    }
+
+    #[test]
+    fn test_rom_size() {
+        let options = AssemblerOptions::new().with_rom_size(8);
+        assert!(assemble(&"D=0\n".repeat(8), options.clone()).is_ok());
+        assert!(matches!(
+            assemble(&"D=0\n".repeat(9), options),
+            Err(AssemblerErrorKind::TooManyInstructions)
+        ));
+    }
+
+    #[test]
+    fn test_symbols_cannot_be_redefined() {
+        let options = AssemblerOptions::new().with_symbol("DELAY", 500);
+        assert!(matches!(
+            assemble("(DELAY)\n@DELAY\n", options),
+            Err(AssemblerErrorKind::SymbolTableSetError(_))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        let options = AssemblerOptions::new().with_variables(16..40000);
+        assert!(matches!(
+            assemble("@0\n", options),
+            Err(AssemblerErrorKind::OptionsError(
+                OptionsError::InvalidVariableRange { .. }
+            ))
+        ));
+
+        let options = AssemblerOptions::new().with_rom_size(0);
+        assert!(matches!(
+            assemble("@0\n", options),
+            Err(AssemblerErrorKind::OptionsError(
+                OptionsError::InvalidRomSize(0)
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_default_variables_end_at_the_screen() {
+        let variables: String = (16..16384).map(|i| format!("@v{}\n", i)).collect();
+        let words = assemble(&variables, AssemblerOptions::new()).unwrap();
+        assert_eq!(words.last(), Some(&16383));
+
+        assert!(matches!(
+            assemble(&format!("{}@last\n", variables), AssemblerOptions::new()),
+            Err(AssemblerErrorKind::TooManyVariables)
+        ));
+    }
+
+    #[test]
+    fn test_default_rom_is_full() {
+        let program = "D=0\n".repeat(32768);
+        assert_eq!(
+            assemble(&program, AssemblerOptions::new()).unwrap().len(),
+            32768
+        );
+
+        let error = assemble(&format!("{}(END)\n", program), AssemblerOptions::new());
+        assert!(matches!(error, Err(AssemblerErrorKind::LabelOutsideOfRom(name)) if name == "END"));
+    }
+}
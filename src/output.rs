@@ -0,0 +1,169 @@
+use std::io::{self, Write};
+
+use crate::{assembler::AssembledProgram, listing::instruction_text};
+
+/// A file format the words of an assembled program can be written in.
+pub trait OutputFormat {
+    /// The extension of files in this format, without the leading dot.
+    fn extension(&self) -> &'static str;
+
+    fn write(&self, program: &AssembledProgram, output: &mut dyn Write) -> io::Result<()>;
+
+    /// Writes the program into memory instead of a file.
+    fn to_bytes(&self, program: &AssembledProgram) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.write(program, &mut bytes)
+            .expect("writing to a Vec<u8> never fails");
+        bytes
+    }
+}
+
+/// The standard .hack format, one 16 digit binary number per line.
+pub struct Hack;
+
+/// The byte order of a [`RawBinary`] image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Endianness {
+    Big,
+    Little,
+}
+
+/// A raw ROM image, two bytes per word without any separators.
+pub struct RawBinary(pub Endianness);
+
+/// One word per line as 4 hexadecimal digits.
+pub struct HexWords;
+
+/// One word per line as an unsigned decimal number.
+pub struct Decimal;
+
+/// The .hack format with a comment holding the address, the decoded instruction
+/// and the source line behind every word.
+///
+/// ```text
+/// 0000000000010000  // 00002  @16               4: @i
+/// ```
+pub struct Annotated;
+
+impl OutputFormat for Hack {
+    fn extension(&self) -> &'static str {
+        "hack"
+    }
+
+    fn write(&self, program: &AssembledProgram, output: &mut dyn Write) -> io::Result<()> {
+        for word in &program.words {
+            writeln!(output, "{:016b}", word)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl OutputFormat for RawBinary {
+    fn extension(&self) -> &'static str {
+        "bin"
+    }
+
+    fn write(&self, program: &AssembledProgram, output: &mut dyn Write) -> io::Result<()> {
+        for word in &program.words {
+            let bytes = match self.0 {
+                Endianness::Big => word.to_be_bytes(),
+                Endianness::Little => word.to_le_bytes(),
+            };
+            output.write_all(&bytes)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl OutputFormat for HexWords {
+    fn extension(&self) -> &'static str {
+        "hex"
+    }
+
+    fn write(&self, program: &AssembledProgram, output: &mut dyn Write) -> io::Result<()> {
+        for word in &program.words {
+            writeln!(output, "{:04X}", word)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl OutputFormat for Decimal {
+    fn extension(&self) -> &'static str {
+        "dec"
+    }
+
+    fn write(&self, program: &AssembledProgram, output: &mut dyn Write) -> io::Result<()> {
+        for word in &program.words {
+            writeln!(output, "{}", word)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl OutputFormat for Annotated {
+    fn extension(&self) -> &'static str {
+        "annotated.hack"
+    }
+
+    fn write(&self, program: &AssembledProgram, output: &mut dyn Write) -> io::Result<()> {
+        let mut previous_line = None;
+
+        for (address, &word) in program.words.iter().enumerate() {
+            write!(
+                output,
+                "{:016b}  // {:05}  {:<16}",
+                word,
+                address,
+                instruction_text(word)
+            )?;
+
+            // like in a listing, a source line is only shown next to the first word it produced
+            if let Some(source) = program.sources.get(address) {
+                let line = (&source.file, source.line);
+                if previous_line != Some(line) {
+                    write!(output, "  {}: {}", source.line, source.source_line.trim())?;
+                    previous_line = Some(line);
+                }
+            }
+
+            writeln!(output)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Assembler;
+
+    #[test]
+    fn test_output_formats() {
+        let program = Assembler::new("@16\nD=-1\n").assemble_program().unwrap();
+
+        let text =
+            |format: &dyn OutputFormat| String::from_utf8(format.to_bytes(&program)).unwrap();
+        assert_eq!(text(&Hack), "0000000000010000\n1110111010010000\n");
+        assert_eq!(text(&HexWords), "0010\nEE90\n");
+        assert_eq!(text(&Decimal), "16\n61072\n");
+        assert_eq!(
+            text(&Annotated).lines().next().unwrap(),
+            "0000000000010000  // 00000  @16               1: @16"
+        );
+
+        assert_eq!(
+            RawBinary(Endianness::Big).to_bytes(&program),
+            [0x00, 0x10, 0xEE, 0x90]
+        );
+        assert_eq!(
+            RawBinary(Endianness::Little).to_bytes(&program),
+            [0x10, 0x00, 0x90, 0xEE]
+        );
+    }
+}
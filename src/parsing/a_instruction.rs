@@ -1,20 +1,86 @@
-use pest::iterators::Pair;
+use pest::{
+    iterators::Pair,
+    prec_climber::{Assoc, Operator as Precedence, PrecClimber},
+    Span,
+};
 
 use crate::{
+    assembler_context::AssemblerError,
     hack_int::HackInt,
-    instructions::{AInstruction, AValue},
+    instructions::{AInstruction, AValue, Operator},
 };
 
-use super::{ParseError, ParsedInstruction, Rule};
+use super::{range, ParsedInstruction, Rule};
 
-pub fn a_instruction(instruction: Pair<Rule>) -> Result<ParsedInstruction, ParseError> {
-    let inner = instruction.into_inner().next().unwrap();
+/// Operators ordered from the lowest to the highest precedence, like in C.
+fn climber() -> PrecClimber<Rule> {
+    PrecClimber::new(vec![
+        Precedence::new(Rule::or, Assoc::Left),
+        Precedence::new(Rule::and, Assoc::Left),
+        Precedence::new(Rule::shl, Assoc::Left) | Precedence::new(Rule::shr, Assoc::Left),
+        Precedence::new(Rule::add, Assoc::Left) | Precedence::new(Rule::sub, Assoc::Left),
+        Precedence::new(Rule::mul, Assoc::Left)
+            | Precedence::new(Rule::div, Assoc::Left)
+            | Precedence::new(Rule::rem, Assoc::Left),
+    ])
+}
 
-    let value = match inner.as_rule() {
-        Rule::symbol => AValue::Symbol(inner.as_str()),
-        Rule::literal => AValue::Literal(HackInt::parse(inner.as_str())?),
+fn operator(rule: Rule) -> Operator {
+    match rule {
+        Rule::add => Operator::Add,
+        Rule::sub => Operator::Sub,
+        Rule::mul => Operator::Mul,
+        Rule::div => Operator::Div,
+        Rule::rem => Operator::Rem,
+        Rule::and => Operator::And,
+        Rule::or => Operator::Or,
+        Rule::shl => Operator::Shl,
+        Rule::shr => Operator::Shr,
+        _ => unreachable!(),
+    }
+}
+
+fn term(term: Pair<Rule>) -> Result<(AValue, Span), AssemblerError> {
+    let span = term.as_span();
+
+    let value = match term.as_rule() {
+        Rule::symbol | Rule::numeric_reference => AValue::Symbol {
+            name: term.as_str().into(),
+            span: range(&span),
+        },
+        Rule::literal => AValue::Literal(
+            HackInt::parse(term.as_str()).map_err(|e| AssemblerError::from(e).with_span(&span))?,
+        ),
+        Rule::expression => return expression(term),
         _ => unreachable!(),
     };
 
-    Ok(ParsedInstruction::AInstruction(AInstruction { value }))
+    Ok((value, span))
+}
+
+pub(super) fn expression(expression: Pair<Rule>) -> Result<(AValue, Span), AssemblerError> {
+    climber().climb(expression.into_inner(), term, |lhs, op, rhs| {
+        let (lhs, lhs_span) = lhs?;
+        let (rhs, rhs_span) = rhs?;
+        let span = lhs_span.start_pos().span(&rhs_span.end_pos());
+
+        let value = AValue::Binary {
+            operator: operator(op.as_rule()),
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+            span: range(&span),
+        };
+
+        Ok((value, span))
+    })
+}
+
+pub fn a_instruction(instruction: Pair<Rule>) -> Result<ParsedInstruction, AssemblerError> {
+    let span = instruction.as_span();
+    let (value, _) = expression(instruction.into_inner().next().unwrap())?;
+
+    Ok(ParsedInstruction::AInstruction(
+        AInstruction { value },
+        range(&span),
+    ))
 }
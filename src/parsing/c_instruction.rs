@@ -1,32 +1,70 @@
 use pest::iterators::Pair;
 
-use crate::instructions::{CInstruction, Computation, JumpType, Register};
-
-use super::{ParsedInstruction, Rule};
-
-fn c_dest(dest: Pair<Rule>) -> Vec<Register> {
-    dest.into_inner()
-        .map(
-            |c| match c.as_str().chars().next().unwrap().to_ascii_uppercase() {
-                'A' => Register::A,
-                'M' => Register::M,
-                'D' => Register::D,
-                _ => unreachable!(),
-            },
-        )
-        .collect()
+use crate::{
+    assembler_context::AssemblerError,
+    instructions::{CInstruction, Computation, JumpType, Register},
+    options::Dialect,
+};
+
+use super::{range, ParseError, ParsedInstruction, Rule};
+
+/// In the strict dialect, rejects `part` unless it is written exactly like `canonical`.
+/// Whitespace is not part of the spelling, so `M + 1` is the same as `M+1`.
+fn check_spelling(
+    part: &Pair<Rule>,
+    canonical: String,
+    dialect: Dialect,
+) -> Result<(), AssemblerError> {
+    let found: String = part.as_str().split_whitespace().collect();
+
+    if dialect == Dialect::Strict && found != canonical {
+        let error = ParseError::NotCanonical { found, canonical };
+        return Err(AssemblerError::from(error).with_span(&part.as_span()));
+    }
+
+    Ok(())
 }
 
-fn c_comp(comp: Pair<Rule>) -> Computation {
-    let comp = comp.into_inner().next().unwrap();
+fn c_dest(dest: Pair<Rule>, dialect: Dialect) -> Result<Vec<Register>, AssemblerError> {
+    let registers: Vec<_> = dest.clone().into_inner().map(register).collect();
 
-    match comp.as_rule() {
-        Rule::constant => constant(comp),
-        Rule::register => Computation::Identity(register(comp)),
-        Rule::unary => unary(comp),
-        Rule::binary => binary(comp),
-        _ => unreachable!(),
+    if (1..registers.len()).any(|i| registers[..i].contains(&registers[i])) {
+        let error = ParseError::DuplicateDestination(dest.as_str().to_string());
+        return Err(AssemblerError::from(error).with_span(&dest.as_span()));
     }
+
+    // the specification orders the destinations as in AMD
+    let canonical = [Register::A, Register::M, Register::D]
+        .into_iter()
+        .filter(|r| registers.contains(r))
+        .map(|r| r.to_string())
+        .collect();
+    check_spelling(&dest, canonical, dialect)?;
+
+    Ok(registers)
+}
+
+/// Reads the computation in the spelling of the Hack specification,
+/// so `A+D` becomes `D+A` and combinations such as `A+M` are rejected.
+pub fn c_comp(comp: Pair<Rule>, dialect: Dialect) -> Result<Computation, AssemblerError> {
+    let span = comp.as_span();
+    let inner = comp.clone().into_inner().next().unwrap();
+
+    let computation = match inner.as_rule() {
+        Rule::constant => constant(inner),
+        Rule::register => Computation::Identity(register(inner)),
+        Rule::unary => unary(inner),
+        Rule::binary => binary(inner),
+        _ => unreachable!(),
+    };
+
+    let computation = computation.canonical().ok_or_else(|| {
+        AssemblerError::from(ParseError::InvalidComputation(span.as_str().to_string()))
+            .with_span(&span)
+    })?;
+    check_spelling(&comp, computation.to_string(), dialect)?;
+
+    Ok(computation)
 }
 
 fn constant(constant: Pair<Rule>) -> Computation {
@@ -89,12 +127,14 @@ fn binary(binary: Pair<Rule>) -> Computation {
         "-" => Computation::Sub(register(reg1), register(reg2)),
         "|" => Computation::Or(register(reg1), register(reg2)),
         "&" => Computation::And(register(reg1), register(reg2)),
-        _ => unimplemented!(),
+        _ => unreachable!(),
     }
 }
 
-fn c_jump(jump: Pair<Rule>) -> JumpType {
-    match jump.as_str().to_ascii_uppercase().as_str() {
+fn c_jump(jump: Pair<Rule>, dialect: Dialect) -> Result<JumpType, AssemblerError> {
+    check_spelling(&jump, jump.as_str().to_ascii_uppercase(), dialect)?;
+
+    let jump = match jump.as_str().to_ascii_uppercase().as_str() {
         "JMP" => JumpType::Jmp,
         "JGT" => JumpType::Jgt,
         "JEQ" => JumpType::Jeq,
@@ -103,10 +143,16 @@ fn c_jump(jump: Pair<Rule>) -> JumpType {
         "JLE" => JumpType::Jle,
         "JNE" => JumpType::Jne,
         _ => unreachable!(),
-    }
+    };
+
+    Ok(jump)
 }
 
-pub fn c_instruction(instruction: Pair<Rule>) -> ParsedInstruction {
+pub fn c_instruction(
+    instruction: Pair<Rule>,
+    dialect: Dialect,
+) -> Result<ParsedInstruction, AssemblerError> {
+    let span = range(&instruction.as_span());
     let c_instr = instruction.into_inner();
 
     let mut destination: Option<Vec<Register>> = None;
@@ -115,16 +161,133 @@ pub fn c_instruction(instruction: Pair<Rule>) -> ParsedInstruction {
 
     for part in c_instr {
         match part.as_rule() {
-            Rule::destination => destination = Some(c_dest(part)),
-            Rule::computation => computation = c_comp(part),
-            Rule::jump => jump = Some(c_jump(part)),
+            Rule::destination => destination = Some(c_dest(part, dialect)?),
+            Rule::computation => computation = c_comp(part, dialect)?,
+            Rule::jump => jump = Some(c_jump(part, dialect)?),
             _ => unreachable!(),
         }
     }
 
-    ParsedInstruction::CInstruction(CInstruction {
-        destination,
-        computation,
-        jump,
-    })
+    Ok(ParsedInstruction::CInstruction(
+        CInstruction {
+            destination,
+            computation,
+            jump,
+        },
+        span,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        assembler_context::AssemblerErrorKind,
+        options::Dialect,
+        parsing::{parse_str, ParseError},
+        Assembler, AssemblerOptions,
+    };
+
+    /// The messages of every error in the program, parsed in the strict dialect.
+    fn strict_errors(program: &str) -> Vec<String> {
+        parse_str(program, Dialect::Strict)
+            .errors
+            .into_iter()
+            .map(|e| e.kind.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_permissive_destinations_in_any_order() {
+        let permissive = Assembler::new("dm=m+1\nAMD=D|A;jgt\n").assemble().unwrap();
+        let canonical = Assembler::new("MD=M+1\nAMD=D|A;JGT\n").assemble().unwrap();
+        assert_eq!(permissive, canonical);
+    }
+
+    #[test]
+    fn test_duplicate_destination() {
+        let error = Assembler::new("DD=M\n").assemble().unwrap_err();
+        assert!(matches!(
+            error.kind,
+            AssemblerErrorKind::ParseError(ParseError::DuplicateDestination(_))
+        ));
+    }
+
+    #[test]
+    fn test_strict_accepts_the_specification() {
+        let program = "MD=M+1\nAMD=D|A;JGT\nD=D+A\nD=D&M\nD=-1\nM=!D\n0;JMP\n";
+        assert_eq!(strict_errors(program), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_strict_ignores_whitespace() {
+        assert_eq!(
+            strict_errors("D = M\nAM = M + 1 ; JNE\n"),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn test_strict_rejects_destination_order() {
+        assert_eq!(
+            strict_errors("DM=M\n"),
+            ["\"DM\" is not spelled like in the Hack specification, write \"MD\""]
+        );
+    }
+
+    #[test]
+    fn test_strict_rejects_lowercase() {
+        assert_eq!(
+            strict_errors("AMD=d;JMP\nM=D;jeq\n"),
+            [
+                "\"d\" is not spelled like in the Hack specification, write \"D\"",
+                "\"jeq\" is not spelled like in the Hack specification, write \"JEQ\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_strict_rejects_swapped_operands() {
+        assert_eq!(
+            strict_errors("D=A+D\nD=M|D\nD=A&D\n"),
+            [
+                "\"A+D\" is not spelled like in the Hack specification, write \"D+A\"",
+                "\"M|D\" is not spelled like in the Hack specification, write \"D|M\"",
+                "\"A&D\" is not spelled like in the Hack specification, write \"D&A\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_strict_from_options() {
+        let strict = AssemblerOptions::new().with_dialect(Dialect::Strict);
+        let error = Assembler::new("D=M|D\n")
+            .with_options(strict)
+            .assemble()
+            .unwrap_err();
+        assert!(matches!(
+            error.kind,
+            AssemblerErrorKind::ParseError(ParseError::NotCanonical { .. })
+        ));
+    }
+
+    #[test]
+    fn test_commutative_operands_in_any_order() {
+        let canonical = Assembler::new("D=D+A\nD=D+M\nD=D&A\nD=D&M\nD=D|A\nD=D|M\n");
+        let swapped = Assembler::new("D=A+D\nD=M+D\nD=A&D\nD=M&D\nD=A|D\nD=M|D\n");
+        assert_eq!(canonical.assemble().unwrap(), swapped.assemble().unwrap());
+    }
+
+    #[test]
+    fn test_computations_the_alu_cannot_do() {
+        for computation in ["A+M", "M-A", "D+D", "A&A"] {
+            let program = format!("D={}\n", computation);
+            let error = Assembler::new(&program).assemble().unwrap_err();
+            assert!(matches!(
+                error.kind,
+                AssemblerErrorKind::ParseError(ParseError::InvalidComputation(c))
+                    if c == computation
+            ));
+            assert_eq!(error.location.unwrap().column, 3);
+        }
+    }
 }
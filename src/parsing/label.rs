@@ -1,13 +1,111 @@
 use pest::iterators::Pair;
 
-use crate::instructions::Label;
+use crate::{
+    assembler_context::AssemblerError,
+    hack_int::HackInt,
+    instructions::{Constant, Label},
+};
 
-use super::Rule;
+use super::{range, Rule};
 
 pub fn label(label: Pair<Rule>) -> Label {
     let label = label.into_inner().next().unwrap();
 
     Label {
-        name: label.as_str(),
+        name: label.as_str().into(),
+        span: range(&label.as_span()),
+    }
+}
+
+pub fn constant(constant: Pair<Rule>) -> Result<Constant, AssemblerError> {
+    let mut parts = constant.into_inner();
+    let name = parts.next().unwrap();
+    let value = parts.next().unwrap();
+
+    Ok(Constant {
+        name: name.as_str(),
+        value: HackInt::parse(value.as_str())
+            .map_err(|e| AssemblerError::from(e).with_span(&value.as_span()))?,
+        span: range(&name.as_span()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        symbol_table::{SymbolKind, SymbolTableSetError},
+        Assembler, AssemblerErrorKind,
+    };
+
+    fn error(program: &str) -> (AssemblerErrorKind, usize) {
+        let error = Assembler::new(program).assemble().unwrap_err();
+        (error.kind, error.location.unwrap().line)
+    }
+
+    #[test]
+    fn test_constant_definition() {
+        let program = ".equ ROWS 5\n.define MASK 0x00FF\n@ROWS\n@MASK\n";
+        let program = Assembler::new(program).assemble_program().unwrap();
+        assert_eq!(program.words, [5, 255]);
+
+        let constants: Vec<_> = program
+            .symbols
+            .iter()
+            .filter(|s| s.kind == SymbolKind::Constant)
+            .map(|s| (s.name.as_str(), s.value))
+            .collect();
+        assert_eq!(constants, [("ROWS", 5), ("MASK", 255)]);
+    }
+
+    #[test]
+    fn test_constant_in_expression() {
+        let program = "@SCREEN+ROW_WORDS*2\n.equ ROW_WORDS 32\n";
+        assert_eq!(Assembler::new(program).assemble().unwrap(), [16448]);
+    }
+
+    #[test]
+    fn test_constant_is_not_a_variable() {
+        // a constant does not take up the address of a variable
+        let program = ".equ ROWS 5\n@ROWS\n@i\n";
+        assert_eq!(Assembler::new(program).assemble().unwrap(), [5, 16]);
+    }
+
+    #[test]
+    fn test_constant_redefined() {
+        let (kind, line) = error(".equ ROWS 5\n.equ ROWS 6\n@ROWS\n");
+        assert!(matches!(
+            kind,
+            AssemblerErrorKind::SymbolTableSetError(SymbolTableSetError::Redefined(name))
+                if name == "ROWS"
+        ));
+        assert_eq!(line, 2);
+    }
+
+    #[test]
+    fn test_constant_redefines_built_in() {
+        let (kind, line) = error(".equ SCREEN 5\n");
+        assert!(matches!(
+            kind,
+            AssemblerErrorKind::SymbolTableSetError(SymbolTableSetError::RedefinedBuiltIn(_))
+        ));
+        assert_eq!(line, 1);
+    }
+
+    #[test]
+    fn test_constant_shadowing_a_label() {
+        let (kind, line) = error("(LOOP)\n@LOOP\n.equ LOOP 5\n");
+        assert!(matches!(
+            kind,
+            AssemblerErrorKind::SymbolTableSetError(SymbolTableSetError::Redefined(name))
+                if name == "LOOP"
+        ));
+        assert_eq!(line, 3);
+    }
+
+    #[test]
+    fn test_constant_too_large() {
+        let (kind, line) = error(".equ BIG 40000\n");
+        assert!(matches!(kind, AssemblerErrorKind::ParseError(_)));
+        assert_eq!(line, 1);
     }
 }
@@ -1,3 +1,7 @@
+use std::ops::Range;
+
+use pest::Span;
+
 use crate::{
     hack_int::ParseHackIntError,
     instructions::{AInstruction, CInstruction},
@@ -7,21 +11,49 @@ mod a_instruction;
 mod c_instruction;
 mod label;
 mod parser;
+mod preprocessor;
+mod pseudo;
 
 use parser::Rule;
 use thiserror::Error;
 
+/// The byte range of a span, which is how the public types refer to the source.
+fn range(span: &Span) -> Range<usize> {
+    span.start()..span.end()
+}
+
+/// An instruction of the program, together with the byte range of the source it was parsed from.
+/// Pseudo-instructions are already lowered into these.
 pub enum ParsedInstruction<'a> {
-    AInstruction(AInstruction<'a>),
-    CInstruction(CInstruction),
+    AInstruction(AInstruction<'a>, Range<usize>),
+    CInstruction(CInstruction, Range<usize>),
+}
+
+impl<'a> ParsedInstruction<'a> {
+    pub fn span(&self) -> &Range<usize> {
+        match self {
+            ParsedInstruction::AInstruction(_, span) | ParsedInstruction::CInstruction(_, span) => {
+                span
+            }
+        }
+    }
 }
 
 #[derive(Error, Debug)]
 pub enum ParseError {
     #[error(transparent)]
     ParseHackIntError(#[from] ParseHackIntError),
-    #[error(transparent)]
-    PestError(#[from] pest::error::Error<Rule>),
+    #[error("{0}")]
+    Syntax(String),
+    #[error("\"{0}\" is not a computation the ALU can perform")]
+    InvalidComputation(String),
+    #[error("destination \"{0}\" names a register more than once")]
+    DuplicateDestination(String),
+    #[error("\"{found}\" is not spelled like in the Hack specification, write \"{canonical}\"")]
+    NotCanonical { found: String, canonical: String },
 }
 
-pub use parser::parse_str;
+pub use parser::{
+    parse_c_instruction, parse_computation, parse_reference, parse_str, ParserOutput,
+};
+pub use preprocessor::{preprocess, IncludeError, MacroError};
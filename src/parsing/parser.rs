@@ -1,41 +1,414 @@
 extern crate pest;
 
-use pest::Parser;
+use std::{collections::HashMap, ops::Range};
 
-use crate::instructions::Label;
+use pest::{
+    error::{Error, ErrorVariant, LineColLocation},
+    iterators::Pair,
+    Parser,
+};
+
+use crate::{
+    assembler_context::{AssemblerError, AssemblerErrorKind},
+    instructions::{AInstruction, CInstruction, Computation, Constant, Label},
+    location::Location,
+    options::Dialect,
+};
 
 use super::{
-    a_instruction::a_instruction, c_instruction::c_instruction, label::label, ParseError,
-    ParsedInstruction,
+    a_instruction::a_instruction,
+    c_instruction::{c_comp, c_instruction},
+    label::{constant, label},
+    pseudo::pseudo,
+    range, ParseError, ParsedInstruction,
 };
 
 #[derive(Parser)]
 #[grammar = "grammar/hack.pest"]
 pub struct HackParser;
 
+/// Everything [`parse_str`] found in a program.
+/// Labels are paired with the index of the instruction they point at.
 pub struct ParserOutput<'a> {
     pub instructions: Vec<ParsedInstruction<'a>>,
     pub labels: Vec<(Label<'a>, usize)>,
+    pub constants: Vec<Constant<'a>>,
+    pub exports: Vec<Label<'a>>,
+    /// The labels of other modules the program refers to.
+    pub imports: Vec<Label<'a>>,
+    /// The byte range of every pseudo-instruction that was lowered into real instructions.
+    pub pseudo_instructions: Vec<Range<usize>>,
+    pub errors: Vec<AssemblerError>,
+}
+
+/// Lists rules the way a human would read them: "a", "a or b", "a, b, or c"
+fn enumerate(rules: &[Rule]) -> String {
+    let mut names: Vec<String> = Vec::new();
+
+    for rule in rules {
+        let name = match rule {
+            // lines are parsed on their own, so the end of the input is the end of the line
+            Rule::EOI => "end of line".to_string(),
+            Rule::add
+            | Rule::sub
+            | Rule::mul
+            | Rule::div
+            | Rule::rem
+            | Rule::and
+            | Rule::or
+            | Rule::shl
+            | Rule::shr => "operator".to_string(),
+            _ => format!("{:?}", rule),
+        };
+
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+
+    match names.as_slice() {
+        [] => String::new(),
+        [single] => single.clone(),
+        [first, second] => format!("{} or {}", first, second),
+        [init @ .., last] => format!("{}, or {}", init.join(", "), last),
+    }
+}
+
+pub(super) fn error_message(variant: ErrorVariant<Rule>) -> String {
+    match variant {
+        ErrorVariant::ParsingError { positives, .. } if !positives.is_empty() => {
+            format!("expected {}", enumerate(&positives))
+        }
+        ErrorVariant::ParsingError { negatives, .. } if !negatives.is_empty() => {
+            format!("unexpected {}", enumerate(&negatives))
+        }
+        ErrorVariant::ParsingError { .. } => "unknown parsing error".to_string(),
+        ErrorVariant::CustomError { message } => message,
+    }
+}
+
+pub(super) fn start_line_col(line_col: &LineColLocation) -> (usize, usize) {
+    match line_col {
+        LineColLocation::Pos(pos) | LineColLocation::Span(pos, _) => *pos,
+    }
+}
+
+fn syntax_error(input: &str, error: Error<Rule>) -> AssemblerError {
+    let (line, column) = start_line_col(&error.line_col);
+
+    AssemblerError::new(ParseError::Syntax(error_message(error.variant)))
+        .with_location(Location::from_line_col(input, line, column))
+}
+
+/// Parses an invalid line on its own to find out what exactly is wrong with it.
+fn invalid_line(input: &str, invalid: Pair<Rule>) -> AssemblerError {
+    let (line, column) = invalid.as_span().start_pos().line_col();
+
+    match HackParser::parse(Rule::line, invalid.as_str()) {
+        Err(error) => {
+            let (_, offset) = start_line_col(&error.line_col);
+
+            AssemblerError::new(ParseError::Syntax(error_message(error.variant)))
+                .with_location(Location::from_line_col(input, line, column + offset - 1))
+        }
+        Ok(_) => AssemblerError::new(ParseError::Syntax("invalid line".to_string()))
+            .with_span(&invalid.as_span()),
+    }
+}
+
+/// Qualifies local and numeric labels while the program is parsed from top to bottom.
+#[derive(Default)]
+struct Scopes<'a> {
+    /// The program, which the byte ranges of labels and references point into.
+    input: &'a str,
+    /// The last label that did not start with a `.`.
+    global: Option<&'a str>,
+    /// How often every numeric label has been defined so far.
+    numeric: HashMap<&'a str, usize>,
+    /// Forward references that have to be defined later on, with the definition they refer to.
+    forward: Vec<(&'a str, usize, Range<usize>)>,
+}
+
+impl<'a> Scopes<'a> {
+    /// `.loop` becomes `GLOBAL.loop` if there is a global label in front of it.
+    fn local(&self, name: &str) -> Option<String> {
+        match self.global {
+            Some(global) if name.starts_with('.') => Some(format!("{}{}", global, name)),
+            _ => None,
+        }
+    }
+
+    fn define(&mut self, mut label: Label<'a>) -> Label<'a> {
+        match self.local(&label.name) {
+            Some(name) => label.name = name.into(),
+            None => self.global = Some(&self.input[label.span.clone()]),
+        }
+
+        label
+    }
+
+    /// The n-th definition of the numeric label `1:` is called `.1$n`.
+    fn define_numeric(&mut self, label: Pair<'a, Rule>) -> Label<'a> {
+        let digits = label.as_str().trim_end_matches(':');
+        let count = self.numeric.entry(digits).or_default();
+        *count += 1;
+
+        Label {
+            name: format!(".{}${}", digits, *count - 1).into(),
+            span: range(&label.as_span()),
+        }
+    }
+
+    /// Resolves a reference such as `.loop`, `1b` or `1f` to the qualified name of its label.
+    fn qualify(
+        &mut self,
+        name: &str,
+        span: &Range<usize>,
+        errors: &mut Vec<AssemblerError>,
+    ) -> Option<String> {
+        if !name.starts_with(|c: char| c.is_ascii_digit()) {
+            return self.local(name);
+        }
+
+        let (digits, direction) = name.split_at(name.len() - 1);
+        let digits = &self.input[span.start..span.start + digits.len()];
+        let defined = self.numeric.get(digits).copied().unwrap_or(0);
+
+        match direction {
+            "f" => self.forward.push((digits, defined, span.clone())),
+            _ if defined == 0 => {
+                let error = AssemblerErrorKind::MissingBackwardLabel(digits.to_string());
+                errors.push(self.error(error, span));
+            }
+            _ => return Some(format!(".{}${}", digits, defined - 1)),
+        }
+
+        Some(format!(".{}${}", digits, defined))
+    }
+
+    fn qualify_instruction(
+        &mut self,
+        instruction: &mut ParsedInstruction<'a>,
+        errors: &mut Vec<AssemblerError>,
+    ) {
+        if let ParsedInstruction::AInstruction(i, _) = instruction {
+            i.value
+                .rename_symbols(&mut |name, span| self.qualify(name, span, errors));
+        }
+    }
+
+    /// Reports every forward reference without a matching label.
+    fn finish(self, errors: &mut Vec<AssemblerError>) {
+        for (digits, definition, span) in &self.forward {
+            if self.numeric.get(digits).copied().unwrap_or(0) <= *definition {
+                let error = AssemblerErrorKind::MissingForwardLabel(digits.to_string());
+                errors.push(self.error(error, span));
+            }
+        }
+    }
+
+    fn error(&self, kind: AssemblerErrorKind, span: &Range<usize>) -> AssemblerError {
+        AssemblerError::new(kind)
+            .with_range(span)
+            .in_source(self.input)
+    }
 }
 
-pub fn parse_str(input: &str) -> Result<ParserOutput, ParseError> {
-    let mut program = HackParser::parse(Rule::program, input)?;
-    let program = program.next().unwrap();
+/// Parses a whole program written in the given dialect.
+/// Syntax errors do not stop the parser, every invalid line is reported in [`ParserOutput::errors`].
+/// The input has to be preprocessed already, includes and macros are not expanded.
+///
+/// ```
+/// # use hack_asm::{parse_str, Dialect, ParsedInstruction};
+/// let output = parse_str("(LOOP)\n@LOOP\nD;JGT\n", Dialect::Permissive);
+/// assert_eq!(output.labels[0].0.name, "LOOP");
+///
+/// let ParsedInstruction::CInstruction(jump, _) = &output.instructions[1] else {
+///     panic!("expected a c-instruction");
+/// };
+/// assert_eq!(jump.to_string(), "D;JGT");
+/// ```
+pub fn parse_str(input: &str, dialect: Dialect) -> ParserOutput<'_> {
     let mut instructions = Vec::new();
     let mut labels = Vec::new();
+    let mut constants = Vec::new();
+    let mut exports = Vec::new();
+    let mut imports = Vec::new();
+    let mut pseudo_instructions = Vec::new();
+    let mut errors = Vec::new();
+    let mut scopes = Scopes {
+        input,
+        ..Default::default()
+    };
+
+    let program = match HackParser::parse(Rule::program, input) {
+        Ok(mut program) => program.next().unwrap(),
+        Err(e) => {
+            errors.push(syntax_error(input, e));
+            return ParserOutput {
+                instructions,
+                labels,
+                constants,
+                exports,
+                imports,
+                pseudo_instructions,
+                errors,
+            };
+        }
+    };
 
     for instruction in program.into_inner() {
         match instruction.as_rule() {
-            Rule::at_instruction => instructions.push(a_instruction(instruction)?),
-            Rule::c_instruction => instructions.push(c_instruction(instruction)),
-            Rule::label => labels.push((label(instruction), instructions.len())),
+            Rule::at_instruction => match a_instruction(instruction) {
+                Ok(mut instruction) => {
+                    scopes.qualify_instruction(&mut instruction, &mut errors);
+                    instructions.push(instruction)
+                }
+                Err(e) => errors.push(e),
+            },
+            Rule::c_instruction => match c_instruction(instruction, dialect) {
+                Ok(instruction) => instructions.push(instruction),
+                Err(e) => errors.push(e),
+            },
+            Rule::pseudo => {
+                pseudo_instructions.push(range(&instruction.as_span()));
+
+                match pseudo(instruction) {
+                    Ok(mut expanded) => {
+                        for instruction in &mut expanded {
+                            scopes.qualify_instruction(instruction, &mut errors);
+                        }
+                        instructions.extend(expanded)
+                    }
+                    Err(e) => errors.push(e),
+                }
+            }
+            Rule::label => {
+                let label = scopes.define(label(instruction));
+                labels.push((label, instructions.len()))
+            }
+            Rule::numeric_label => {
+                let label = scopes.define_numeric(instruction);
+                labels.push((label, instructions.len()))
+            }
+            Rule::equ => match constant(instruction) {
+                Ok(constant) => constants.push(constant),
+                Err(e) => errors.push(e),
+            },
+            Rule::export => exports.extend(instruction.into_inner().map(|symbol| Label {
+                name: symbol.as_str().into(),
+                span: range(&symbol.as_span()),
+            })),
+            Rule::import => imports.extend(instruction.into_inner().map(|symbol| Label {
+                name: symbol.as_str().into(),
+                span: range(&symbol.as_span()),
+            })),
+            Rule::invalid => errors.push(invalid_line(input, instruction)),
             Rule::EOI => (),
             _ => unreachable!(),
         };
     }
 
-    Ok(ParserOutput {
+    scopes.finish(&mut errors);
+
+    ParserOutput {
         instructions,
         labels,
-    })
+        constants,
+        exports,
+        imports,
+        pseudo_instructions,
+        errors,
+    }
+}
+
+/// Parses a single a-instruction that was left unresolved in an object file.
+pub fn parse_reference(input: &str) -> Result<AInstruction<'_>, AssemblerError> {
+    let reference = HackParser::parse(Rule::reference, input)
+        .map_err(|e| syntax_error(input, e))?
+        .next()
+        .unwrap();
+
+    match a_instruction(reference.into_inner().next().unwrap())? {
+        ParsedInstruction::AInstruction(instruction, _) => Ok(instruction),
+        ParsedInstruction::CInstruction(..) => unreachable!(),
+    }
+}
+
+/// Parses a single c-instruction such as `AM=M-1;JNE` in the permissive dialect.
+pub fn parse_c_instruction(input: &str) -> Result<CInstruction, AssemblerError> {
+    let instruction = HackParser::parse(Rule::single_c_instruction, input)
+        .map_err(|e| syntax_error(input, e))?
+        .next()
+        .unwrap();
+
+    match c_instruction(
+        instruction.into_inner().next().unwrap(),
+        Dialect::Permissive,
+    )? {
+        ParsedInstruction::CInstruction(instruction, _) => Ok(instruction),
+        ParsedInstruction::AInstruction(..) => unreachable!(),
+    }
+}
+
+/// Parses a single computation such as `D+M` in the permissive dialect.
+pub fn parse_computation(input: &str) -> Result<Computation, AssemblerError> {
+    let computation = HackParser::parse(Rule::single_computation, input)
+        .map_err(|e| syntax_error(input, e))?
+        .next()
+        .unwrap();
+
+    c_comp(
+        computation.into_inner().next().unwrap(),
+        Dialect::Permissive,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{AssembledProgram, Assembler, AssemblerErrorKind};
+
+    fn assemble(program: &str) -> AssembledProgram {
+        Assembler::new(program).assemble_program().unwrap()
+    }
+
+    fn names(program: &AssembledProgram) -> Vec<&str> {
+        program.symbols.iter().map(|s| s.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_local_labels_belong_to_the_label_in_front() {
+        let program = assemble("(MULT)\n(.loop)\n@.loop\n(DIV)\n(.loop)\n@.loop\n");
+        assert_eq!(program.words, [0, 1]);
+        assert_eq!(names(&program), ["MULT", "MULT.loop", "DIV", "DIV.loop"]);
+    }
+
+    #[test]
+    fn test_local_label_by_its_full_name() {
+        let program = assemble("(MULT)\n(.loop)\nD=0\n(DIV)\n@MULT.loop\n");
+        assert_eq!(program.words[1], 0);
+    }
+
+    #[test]
+    fn test_numeric_labels() {
+        let program = assemble("1:\n@1b\n@1f\n1:\n@1b\n");
+        assert_eq!(program.words, [0, 2, 2]);
+        assert_eq!(names(&program), [".1$0", ".1$1"]);
+    }
+
+    #[test]
+    fn test_hash_in_symbol_names() {
+        let program = assemble("@#count\n(LOOP#1)\n@LOOP#1\n@#count%3\n");
+        assert_eq!(program.words, [16, 1, 1]);
+        assert_eq!(names(&program), ["LOOP#1", "#count"]);
+    }
+
+    #[test]
+    fn test_missing_forward_label() {
+        let error = Assembler::new("1:\n@1f\n").assemble().unwrap_err();
+        assert!(matches!(
+            error.kind,
+            AssemblerErrorKind::MissingForwardLabel(_)
+        ));
+    }
 }
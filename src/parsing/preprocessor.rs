@@ -0,0 +1,807 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use pest::{error::Error, Parser};
+use thiserror::Error;
+
+use crate::{
+    assembler_context::{AssemblerError, AssemblerErrorKind},
+    diagnostic::Note,
+    location::Location,
+};
+
+use super::{
+    parser::{error_message, start_line_col, HackParser, Rule},
+    ParseError,
+};
+
+/// How deeply macros may call each other before the expansion is aborted.
+pub const MAX_MACRO_DEPTH: usize = 64;
+
+#[derive(Error, Debug)]
+pub enum MacroError {
+    #[error("macro \"{0}\" is already defined")]
+    Redefined(String),
+    #[error("macro \"{0}\" is never closed with .endm")]
+    Unterminated(String),
+    #[error(".endm without a matching .macro")]
+    UnmatchedEnd,
+    #[error("macros cannot be defined inside of the macro \"{0}\"")]
+    Nested(String),
+    #[error("parameter \"{0}\" is declared more than once")]
+    DuplicateParameter(String),
+    #[error("macro expects {expected} argument(s) but {given} were given")]
+    ArgumentCount { expected: usize, given: usize },
+    #[error("expanding macro \"{0}\" exceeds the maximum depth of {MAX_MACRO_DEPTH}")]
+    TooDeep(String),
+}
+
+#[derive(Error, Debug)]
+pub enum IncludeError {
+    #[error("file \"{0}\" not found")]
+    NotFound(String),
+    #[error("error reading {}: {}", .0.display(), .1)]
+    Io(PathBuf, std::io::Error),
+    #[error("include cycle, {} is already being included", .0.display())]
+    Cycle(PathBuf),
+}
+
+/// A part of an expanded line. Starting at the column `expanded` it either continues
+/// the original text at `original`, or replaces the token of `length` characters there.
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+    expanded: usize,
+    original: usize,
+    length: Option<usize>,
+}
+
+/// The line of the original source a line of the preprocessed text was created from.
+#[derive(Debug, Clone)]
+struct SourceLine {
+    /// The file the line belongs to, `None` if the program has no file name.
+    file: Option<String>,
+    line: usize,
+    text: String,
+    segments: Vec<Segment>,
+    /// The macro calls this line was expanded from, innermost first.
+    expansions: Vec<Note>,
+}
+
+impl SourceLine {
+    fn verbatim(file: Option<&str>, line: usize, text: &str) -> Self {
+        Self {
+            file: file.map(str::to_string),
+            line,
+            text: text.to_string(),
+            segments: Vec::new(),
+            expansions: Vec::new(),
+        }
+    }
+
+    /// Maps a 0-based column of the expanded line onto the first and the last
+    /// column of the original text it was created from.
+    fn columns(&self, column: usize) -> (usize, usize) {
+        match self.segments.partition_point(|s| s.expanded <= column) {
+            0 => (column, column),
+            i => {
+                let segment = self.segments[i - 1];
+                match segment.length {
+                    Some(length) => (segment.original, segment.original + length - 1),
+                    None => {
+                        let column = segment.original + column - segment.expanded;
+                        (column, column)
+                    }
+                }
+            }
+        }
+    }
+
+    /// The original location of `length` characters starting at the 1-based `column`.
+    fn location(&self, column: usize, length: usize) -> Location {
+        let (start, _) = self.columns(column - 1);
+        let (_, end) = self.columns(column + length.max(1) - 2);
+        let remaining = self.text.chars().count().saturating_sub(start);
+
+        Location {
+            file: self.file.clone(),
+            line: self.line,
+            column: start + 1,
+            source_line: self.text.clone(),
+            length: (end + 1).saturating_sub(start).min(remaining).max(1),
+        }
+    }
+}
+
+/// Maps locations inside of the preprocessed text back onto the original source.
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    lines: Vec<SourceLine>,
+}
+
+impl SourceMap {
+    fn source(&self, location: &Location) -> Option<&SourceLine> {
+        self.lines.get(location.line.checked_sub(1)?)
+    }
+
+    /// The original location together with the macro calls it was expanded from.
+    pub fn locate(&self, location: Location) -> (Location, Vec<Note>) {
+        match self.source(&location) {
+            Some(source) => (
+                source.location(location.column, location.length),
+                source.expansions.clone(),
+            ),
+            None => (location, Vec::new()),
+        }
+    }
+
+    /// Moves the location and notes of an error onto the original source.
+    pub fn locate_error(&self, mut error: AssemblerError) -> AssemblerError {
+        match error.location.take() {
+            Some(location) => {
+                let (location, notes) = self.locate(*location);
+                error.with_location(location).with_notes(notes)
+            }
+            None => error,
+        }
+    }
+
+    /// The line that was written in the original source, which is the outermost
+    /// macro call for expanded lines.
+    pub fn call_site(&self, location: Location) -> Location {
+        let (location, notes) = self.locate(location);
+        notes
+            .into_iter()
+            .last()
+            .map_or(location, |note| note.location)
+    }
+}
+
+/// A program with every file included and every macro expanded.
+pub struct Preprocessed {
+    pub text: String,
+    pub source_map: SourceMap,
+    /// Errors in the includes, macro definitions and calls, already located in the original source.
+    pub errors: Vec<AssemblerError>,
+}
+
+struct Macro {
+    parameters: Vec<String>,
+    /// Every line between `.macro` and `.endm`.
+    body: Vec<SourceLine>,
+    /// Labels defined inside of the body, they get a unique name in every expansion.
+    labels: Vec<String>,
+    /// The numeric labels defined inside of the body, with the index of their line.
+    numeric_labels: Vec<(usize, String)>,
+}
+
+#[derive(Default)]
+struct Preprocessor<'a> {
+    include_dirs: &'a [PathBuf],
+    /// The files that are currently being included, to detect cycles.
+    including: Vec<PathBuf>,
+    macros: HashMap<String, Rc<Macro>>,
+    text: String,
+    lines: Vec<SourceLine>,
+    errors: Vec<AssemblerError>,
+    expansions: usize,
+    /// Set once the maximum depth was exceeded, to stop recursive macros from growing exponentially.
+    aborted: bool,
+}
+
+fn is_symbol_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "._$:#".contains(c)
+}
+
+fn is_directive(text: &str, directive: &str) -> bool {
+    text.trim_start()
+        .strip_prefix(directive)
+        .is_some_and(|rest| !rest.starts_with(is_symbol_char))
+}
+
+fn syntax_error(source: &SourceLine, error: Error<Rule>) -> AssemblerError {
+    let (_, column) = start_line_col(&error.line_col);
+
+    AssemblerError::new(ParseError::Syntax(error_message(error.variant)))
+        .with_location(source.location(column, 1))
+        .with_notes(source.expansions.clone())
+}
+
+/// The location of the whole line, without the surrounding whitespace.
+fn whole_line(source: &SourceLine) -> Location {
+    let indentation = source.text.len() - source.text.trim_start().len();
+    let column = source.text[..indentation].chars().count() + 1;
+
+    Location {
+        file: source.file.clone(),
+        line: source.line,
+        column,
+        source_line: source.text.clone(),
+        length: source.text.trim().chars().count().max(1),
+    }
+}
+
+fn macro_error(source: &SourceLine, error: impl Into<AssemblerErrorKind>) -> AssemblerError {
+    AssemblerError::new(error)
+        .with_location(whole_line(source))
+        .with_notes(source.expansions.clone())
+}
+
+fn defined_label(text: &str) -> Option<&str> {
+    let mut label = HackParser::parse(Rule::label, text.trim_start()).ok()?;
+    Some(label.next()?.into_inner().next()?.as_str())
+}
+
+/// The digits of a numeric label such as `1:`, if the line defines one.
+fn defined_numeric_label(text: &str) -> Option<&str> {
+    let label = HackParser::parse(Rule::numeric_label, text.trim_start()).ok()?;
+    Some(label.as_str().trim_end_matches(':'))
+}
+
+impl<'a> Preprocessor<'a> {
+    /// Finds an included file next to the including file or inside of the include directories.
+    fn resolve(&self, file: Option<&str>, included: &str) -> Option<PathBuf> {
+        let directory = file
+            .and_then(|file| Path::new(file).parent())
+            .unwrap_or(Path::new(""));
+
+        std::iter::once(directory)
+            .chain(self.include_dirs.iter().map(PathBuf::as_path))
+            .map(|directory| directory.join(included))
+            .find(|path| path.is_file())
+    }
+
+    /// Reads the file an `.include` line refers to.
+    fn include(&mut self, source: &SourceLine) -> Result<Vec<SourceLine>, AssemblerError> {
+        let include =
+            HackParser::parse(Rule::include, &source.text).map_err(|e| syntax_error(source, e))?;
+        let included = include
+            .flatten()
+            .find(|p| p.as_rule() == Rule::path)
+            .unwrap()
+            .as_str();
+
+        let path = self
+            .resolve(source.file.as_deref(), included)
+            .ok_or_else(|| macro_error(source, IncludeError::NotFound(included.to_string())))?;
+        let canonical = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+
+        if self.including.contains(&canonical) {
+            return Err(macro_error(source, IncludeError::Cycle(path)));
+        }
+
+        let input = match fs::read_to_string(&path) {
+            Ok(input) => input,
+            Err(e) => return Err(macro_error(source, IncludeError::Io(path, e))),
+        };
+
+        self.including.push(canonical);
+        let lines = self.read_lines(&input, Some(&path.display().to_string()));
+        self.including.pop();
+
+        Ok(lines)
+    }
+
+    /// Splits a file into lines, replacing every `.include` with the lines of the included file.
+    fn read_lines(&mut self, input: &str, file: Option<&str>) -> Vec<SourceLine> {
+        let mut lines = Vec::new();
+
+        for (index, text) in input.lines().enumerate() {
+            let source = SourceLine::verbatim(file, index + 1, text);
+
+            if !is_directive(text, ".include") {
+                lines.push(source);
+                continue;
+            }
+
+            match self.include(&source) {
+                Ok(included) => lines.extend(included),
+                Err(e) => self.errors.push(e),
+            }
+        }
+
+        lines
+    }
+
+    /// Removes every macro definition from the program and returns the remaining lines.
+    fn collect_macros(&mut self, lines: Vec<SourceLine>) -> Vec<SourceLine> {
+        let mut remaining = Vec::new();
+        let mut current: Option<(String, Macro, SourceLine)> = None;
+
+        for source in lines {
+            let text = source.text.as_str();
+
+            if is_directive(text, ".macro") {
+                if let Some((name, _, _)) = &current {
+                    let error = MacroError::Nested(name.clone());
+                    self.errors.push(macro_error(&source, error));
+                    continue;
+                }
+
+                let definition = match HackParser::parse(Rule::macro_definition, text) {
+                    Ok(mut definition) => definition.next().unwrap(),
+                    Err(e) => {
+                        self.errors.push(syntax_error(&source, e));
+                        continue;
+                    }
+                };
+
+                let mut symbols = definition
+                    .into_inner()
+                    .filter(|s| s.as_rule() == Rule::symbol)
+                    .map(|s| s.as_str().to_string());
+                let name = symbols.next().unwrap();
+                let mut parameters = Vec::new();
+
+                for parameter in symbols {
+                    if parameters.contains(&parameter) {
+                        let error = MacroError::DuplicateParameter(parameter.clone());
+                        self.errors.push(macro_error(&source, error));
+                    }
+                    parameters.push(parameter);
+                }
+
+                let body = Macro {
+                    parameters,
+                    body: Vec::new(),
+                    labels: Vec::new(),
+                    numeric_labels: Vec::new(),
+                };
+                current = Some((name, body, source));
+            } else if is_directive(text, ".endm") {
+                if let Err(e) = HackParser::parse(Rule::macro_end, text) {
+                    self.errors.push(syntax_error(&source, e));
+                }
+
+                match current.take() {
+                    Some((name, _, definition)) if self.macros.contains_key(&name) => {
+                        let error = MacroError::Redefined(name);
+                        self.errors.push(macro_error(&definition, error));
+                    }
+                    Some((name, body, _)) => {
+                        self.macros.insert(name, Rc::new(body));
+                    }
+                    None => self
+                        .errors
+                        .push(macro_error(&source, MacroError::UnmatchedEnd)),
+                }
+            } else if let Some((_, body, _)) = &mut current {
+                body.labels.extend(defined_label(text).map(str::to_string));
+                if let Some(digits) = defined_numeric_label(text) {
+                    body.numeric_labels
+                        .push((body.body.len(), digits.to_string()));
+                }
+                body.body.push(source);
+            } else {
+                remaining.push(source);
+            }
+        }
+
+        if let Some((name, _, definition)) = current {
+            let error = MacroError::Unterminated(name);
+            self.errors.push(macro_error(&definition, error));
+        }
+
+        remaining
+    }
+
+    /// The name of the macro that is called by the line, if any.
+    fn called_macro(&self, text: &str) -> Option<(String, Rc<Macro>)> {
+        let text = text.trim_start();
+        let end = text.find(|c| !is_symbol_char(c)).unwrap_or(text.len());
+        let rest = &text[end..];
+
+        if !(rest.is_empty() || rest.starts_with(char::is_whitespace) || rest.starts_with("//")) {
+            return None;
+        }
+
+        self.macros
+            .get_key_value(&text[..end])
+            .map(|(name, body)| (name.clone(), body.clone()))
+    }
+
+    /// Emits a line, expanding it first if it calls a macro.
+    fn line(&mut self, text: String, source: SourceLine, depth: usize) {
+        if self.aborted {
+            return;
+        }
+
+        let Some((name, body)) = self.called_macro(&text) else {
+            self.text.push_str(&text);
+            self.text.push('\n');
+            self.lines.push(source);
+            return;
+        };
+
+        let call = match HackParser::parse(Rule::macro_call, &text) {
+            Ok(mut call) => call.next().unwrap(),
+            Err(e) => {
+                self.errors.push(syntax_error(&source, e));
+                return;
+            }
+        };
+        let arguments: Vec<_> = call
+            .into_inner()
+            .filter(|a| a.as_rule() == Rule::argument)
+            .map(|a| a.as_str().trim())
+            .collect();
+
+        if arguments.len() != body.parameters.len() {
+            let error = MacroError::ArgumentCount {
+                expected: body.parameters.len(),
+                given: arguments.len(),
+            };
+            self.errors.push(macro_error(&source, error));
+            return;
+        }
+
+        if depth >= MAX_MACRO_DEPTH {
+            // no notes, the whole chain of calls would repeat the same few lines
+            let error =
+                AssemblerError::new(MacroError::TooDeep(name)).with_location(whole_line(&source));
+            self.errors.push(error);
+            self.aborted = true;
+            return;
+        }
+
+        // read once, a nested call inside of the body counts as another expansion
+        self.expansions += 1;
+        let id = self.expansions;
+        let mut expansions = vec![Note {
+            message: format!("in this expansion of macro \"{}\"", name),
+            location: whole_line(&source),
+        }];
+        expansions.extend(source.expansions);
+
+        for (index, line) in body.body.iter().enumerate() {
+            let (expanded, segments) = self.substitute(&name, id, &body, &arguments, index);
+            let source = SourceLine {
+                segments,
+                expansions: expansions.clone(),
+                ..line.clone()
+            };
+
+            self.line(expanded, source, depth + 1);
+        }
+    }
+
+    /// The name of a label defined in the body that is unique to this expansion,
+    /// `LOOP` becomes `NAME$n$LOOP` and `.loop` becomes `.NAME$n$loop`,
+    /// so a local label stays local to the label in front of the call.
+    fn unique_label(name: &str, id: usize, label: &str) -> String {
+        match label.strip_prefix('.') {
+            Some(local) => format!(".{}${}${}", name, id, local),
+            None => format!("{}${}${}", name, id, label),
+        }
+    }
+
+    /// Replaces a numeric label of the body or a reference to one with a local label that is
+    /// unique to this expansion, the k-th `1:` of the body becomes `(.NAME$n$1$k)`.
+    /// `1b` and `1f` are only replaced if the body defines a matching label in that direction,
+    /// so they cannot refer to a numeric label of the code around the call.
+    fn numeric_label(
+        name: &str,
+        id: usize,
+        body: &Macro,
+        line: usize,
+        token: &str,
+    ) -> Option<String> {
+        let (digits, direction) = match token.strip_suffix(':') {
+            Some(digits) => (digits, ":"),
+            None => token.split_at(token.len() - 1),
+        };
+        if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+
+        let mut matching = body
+            .numeric_labels
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, d))| d == digits);
+        let definition = match direction {
+            ":" => matching.find(|(_, (l, _))| *l == line),
+            "b" => matching.rfind(|(_, (l, _))| *l < line),
+            "f" => matching.find(|(_, (l, _))| *l > line),
+            _ => None,
+        };
+
+        let (index, _) = definition?;
+        let occurrence = body.numeric_labels[..index]
+            .iter()
+            .filter(|(_, d)| d == digits)
+            .count();
+        let label = format!(".{}${}${}${}", name, id, digits, occurrence);
+
+        match direction {
+            ":" => Some(format!("({})", label)),
+            _ => Some(label),
+        }
+    }
+
+    /// Replaces the parameters inside of a line of the body with the arguments of the call
+    /// and gives every label that is defined in the body a name unique to this expansion.
+    fn substitute(
+        &self,
+        name: &str,
+        id: usize,
+        body: &Macro,
+        arguments: &[&str],
+        line: usize,
+    ) -> (String, Vec<Segment>) {
+        let text = &body.body[line].text;
+        let mut expanded = String::new();
+        let mut segments = Vec::new();
+        let mut length = 0;
+
+        // comments are copied as they are
+        let (code, comment) = text.split_at(text.find("//").unwrap_or(text.len()));
+        let chars: Vec<char> = code.chars().collect();
+        let mut column = 0;
+
+        while column < chars.len() {
+            let end = match chars[column] {
+                // so is the content of character literals
+                '\'' => chars[column + 1..]
+                    .iter()
+                    .position(|&c| c == '\'')
+                    .map_or(chars.len(), |i| column + i + 2),
+                c if is_symbol_char(c) => chars[column..]
+                    .iter()
+                    .position(|&c| !is_symbol_char(c))
+                    .map_or(chars.len(), |i| column + i),
+                _ => column + 1,
+            };
+            let token: String = chars[column..end].iter().collect();
+
+            let replacement = match body.parameters.iter().position(|p| *p == token) {
+                Some(i) => Some(arguments[i].to_string()),
+                None if body.labels.contains(&token) => Some(Self::unique_label(name, id, &token)),
+                None if token.starts_with(|c: char| c.is_ascii_digit()) => {
+                    Self::numeric_label(name, id, body, line, &token)
+                }
+                None => None,
+            };
+
+            match replacement {
+                Some(replacement) => {
+                    segments.push(Segment {
+                        expanded: length,
+                        original: column,
+                        length: Some(end - column),
+                    });
+                    length += replacement.chars().count();
+                    expanded.push_str(&replacement);
+                    segments.push(Segment {
+                        expanded: length,
+                        original: end,
+                        length: None,
+                    });
+                }
+                None => {
+                    length += end - column;
+                    expanded.push_str(&token);
+                }
+            }
+
+            column = end;
+        }
+
+        expanded.push_str(comment);
+        (expanded, segments)
+    }
+}
+
+/// Includes every file and expands every macro of the program.
+///
+/// `.include "file.asm"` is replaced by the lines of the file, which is looked up next to
+/// the including file first and inside of `include_dirs` afterwards. `file` is the path of the
+/// program itself, without it relative includes are resolved against the working directory.
+///
+/// A macro is defined with `.macro NAME param1, param2` and ends at `.endm`.
+/// A line starting with the name of a macro is replaced by its body,
+/// with every parameter replaced by the matching argument of the call.
+pub fn preprocess(input: &str, file: Option<&str>, include_dirs: &[PathBuf]) -> Preprocessed {
+    let mut preprocessor = Preprocessor {
+        include_dirs,
+        ..Default::default()
+    };
+
+    if let Some(path) = file.and_then(|file| fs::canonicalize(file).ok()) {
+        preprocessor.including.push(path);
+    }
+
+    let lines = preprocessor.read_lines(input, file);
+    for source in preprocessor.collect_macros(lines) {
+        preprocessor.line(source.text.clone(), source, 0);
+    }
+
+    Preprocessed {
+        text: preprocessor.text,
+        source_map: SourceMap {
+            lines: preprocessor.lines,
+        },
+        errors: preprocessor.errors,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_dir::TestDir, Assembler, AssemblerErrorKind};
+
+    const WAIT: &str = ".macro WAIT counter\n(.loop)\n@counter\nMD=M-1\n@.loop\nD;JGT\n.endm\n";
+
+    fn expand(program: &str) -> String {
+        let preprocessed = preprocess(program, None, &[]);
+        assert!(preprocessed.errors.is_empty());
+        preprocessed.text
+    }
+
+    #[test]
+    fn test_parameters_are_replaced() {
+        let program =
+            ".macro STORE value, address\n@value\nD=A\n@address\nM=D\n.endm\nSTORE 5, R1\n";
+        assert_eq!(expand(program), "@5\nD=A\n@R1\nM=D\n");
+    }
+
+    #[test]
+    fn test_remainder_of_a_parameter() {
+        let program = ".macro MOD3 value\n@value%3\n.endm\nMOD3 Y\n";
+        assert_eq!(expand(program), "@Y%3\n");
+    }
+
+    #[test]
+    fn test_labels_are_unique_to_every_expansion() {
+        let program = ".macro HALT\n(END)\n@END\n0;JMP\n.endm\nHALT\nHALT\n";
+        assert_eq!(
+            expand(program),
+            "(HALT$1$END)\n@HALT$1$END\n0;JMP\n(HALT$2$END)\n@HALT$2$END\n0;JMP\n"
+        );
+    }
+
+    #[test]
+    fn test_every_expansion_jumps_to_its_own_loop() {
+        let program = format!("{}WAIT R13\nWAIT R14\n", WAIT.replace(".loop", "LOOP"));
+        let words = Assembler::new(&program).assemble().unwrap();
+        assert_eq!((words[0], words[2]), (13, 0));
+        assert_eq!((words[4], words[6]), (14, 4));
+        assert_eq!((words[1], words[3]), (words[5], words[7]));
+    }
+
+    #[test]
+    fn test_nested_calls() {
+        let program = ".macro LOAD value\n@value\n.endm\n.macro TWICE value\nLOAD value\nLOAD value\n.endm\nTWICE 3\n";
+        assert_eq!(expand(program), "@3\n@3\n");
+    }
+
+    #[test]
+    fn test_depth_limit() {
+        let preprocessed = preprocess(".macro AGAIN\nAGAIN\n.endm\nAGAIN\n", None, &[]);
+        // the expansion is aborted after the first error
+        assert_eq!(preprocessed.errors.len(), 1);
+        assert!(matches!(
+            &preprocessed.errors[0].kind,
+            AssemblerErrorKind::MacroError(MacroError::TooDeep(name)) if name == "AGAIN"
+        ));
+    }
+
+    #[test]
+    fn test_argument_count() {
+        let preprocessed = preprocess(".macro LOAD value\n@value\n.endm\nLOAD 1, 2\n", None, &[]);
+        assert!(matches!(
+            preprocessed.errors[0].kind,
+            AssemblerErrorKind::MacroError(MacroError::ArgumentCount {
+                expected: 1,
+                given: 2
+            })
+        ));
+        assert_eq!(preprocessed.errors[0].location.as_ref().unwrap().line, 4);
+    }
+
+    #[test]
+    fn test_errors_inside_of_an_expansion() {
+        let error = Assembler::new(".macro LOAD addr\n@addr\n.endm\nLOAD 40000\n")
+            .assemble()
+            .unwrap_err();
+        // the error points at the body, with a note at the call
+        assert_eq!(error.location.unwrap().line, 2);
+        assert_eq!(error.notes[0].location.line, 4);
+    }
+
+    #[test]
+    fn test_labels_around_a_nested_call() {
+        let program =
+            ".macro INNER\nD=0\n.endm\n.macro OUTER\n(LOOP)\nINNER\n@LOOP\nD;JGT\n.endm\nOUTER\n";
+        assert_eq!(
+            expand(program),
+            "(OUTER$1$LOOP)\nD=0\n@OUTER$1$LOOP\nD;JGT\n"
+        );
+        assert_eq!(
+            Assembler::new(program).assemble().unwrap(),
+            [0xEA90, 0, 0xE301]
+        );
+    }
+
+    #[test]
+    fn test_hash_in_labels_of_the_body() {
+        let program = ".macro HALT\n(END#)\n@END#\n.endm\nHALT\n";
+        assert_eq!(expand(program), "(HALT$1$END#)\n@HALT$1$END#\n");
+    }
+
+    #[test]
+    fn test_local_labels_stay_local() {
+        let program = format!("{}(MAIN)\nWAIT R13\n(.end)\n@.end\n", WAIT);
+        assert_eq!(
+            expand(&program),
+            "(MAIN)\n(.WAIT$1$loop)\n@R13\nMD=M-1\n@.WAIT$1$loop\nD;JGT\n(.end)\n@.end\n"
+        );
+
+        // the label after the call is still local to MAIN
+        let program = Assembler::new(&program).assemble_program().unwrap();
+        let names: Vec<_> = program.symbols.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["MAIN", "MAIN.WAIT$1$loop", "MAIN.end"]);
+    }
+
+    #[test]
+    fn test_numeric_labels_are_unique_to_every_expansion() {
+        let program = ".macro SKIP\n@1f\n0;JMP\n1:\nD=0\n.endm\n@1f\n0;JMP\nSKIP\n1:\n@1b\n";
+        assert_eq!(
+            expand(program),
+            "@1f\n0;JMP\n@.SKIP$1$1$0\n0;JMP\n(.SKIP$1$1$0)\nD=0\n1:\n@1b\n"
+        );
+
+        // the jumps around the call skip the label inside of it
+        let words = Assembler::new(program).assemble().unwrap();
+        assert_eq!(words, [5, 0xEA87, 4, 0xEA87, 0xEA90, 5]);
+    }
+
+    #[test]
+    fn test_numeric_labels_defined_twice() {
+        let program = ".macro TWICE\n1:\n@1b\n1:\n@1b\n.endm\nTWICE\n";
+        assert_eq!(
+            expand(program),
+            "(.TWICE$1$1$0)\n@.TWICE$1$1$0\n(.TWICE$1$1$1)\n@.TWICE$1$1$1\n"
+        );
+    }
+
+    #[test]
+    fn test_numeric_reference_outside_of_the_body() {
+        // without a 1: in the body, 1b refers to the code in front of the call
+        let program = ".macro BACK\n@1b\n.endm\n1:\nBACK\n";
+        assert_eq!(expand(program), "1:\n@1b\n");
+    }
+
+    #[test]
+    fn test_include_relative_to_the_file() {
+        let dir = TestDir::new("include_relative_to_the_file");
+        dir.write("lib/counter.asm", "(INC)\n@counter\nM=M+1\n");
+        let main = dir.write("main.asm", "");
+
+        let words = Assembler::new("@counter\n.include \"lib/counter.asm\"\n@INC\n")
+            .with_file_name(&main)
+            .assemble()
+            .unwrap();
+        // the variable is shared with the included file
+        assert_eq!(words[0..2], [16, 16]);
+        assert_eq!(words[3], 1);
+    }
+
+    #[test]
+    fn test_include_cycle() {
+        let dir = TestDir::new("include_cycle");
+        dir.write("lib/cycle.asm", ".include \"cycle.asm\"\n");
+        let main = dir.write("main.asm", "");
+
+        let error = Assembler::new(".include \"cycle.asm\"\n")
+            .with_file_name(&main)
+            .with_include_dir(dir.path().join("lib"))
+            .assemble()
+            .unwrap_err();
+        assert!(matches!(error.kind, AssemblerErrorKind::IncludeError(_)));
+        assert!(error.location.unwrap().file.unwrap().ends_with("cycle.asm"));
+    }
+}
@@ -0,0 +1,304 @@
+use std::ops::Range;
+
+use pest::iterators::{Pair, Pairs};
+
+use crate::{
+    assembler_context::AssemblerError,
+    hack_int::HackInt,
+    instructions::{AInstruction, AValue, CInstruction, Computation, JumpType, Register},
+};
+
+use super::{a_instruction::expression, range, ParsedInstruction, Rule};
+
+fn a<'a>(value: u16, span: &Range<usize>) -> ParsedInstruction<'a> {
+    let value = AValue::Literal(HackInt::new_unchecked(value));
+    ParsedInstruction::AInstruction(AInstruction { value }, span.clone())
+}
+
+fn c<'a>(
+    register: Register,
+    computation: Computation,
+    span: &Range<usize>,
+) -> ParsedInstruction<'a> {
+    c_multi(&[register], computation, span)
+}
+
+fn c_multi<'a>(
+    destination: &[Register],
+    computation: Computation,
+    span: &Range<usize>,
+) -> ParsedInstruction<'a> {
+    let instruction = CInstruction {
+        destination: Some(destination.to_vec()),
+        computation,
+        jump: None,
+    };
+    ParsedInstruction::CInstruction(instruction, span.clone())
+}
+
+fn jump<'a>(
+    computation: Computation,
+    jump: JumpType,
+    span: &Range<usize>,
+) -> ParsedInstruction<'a> {
+    let instruction = CInstruction {
+        destination: None,
+        computation,
+        jump: Some(jump),
+    };
+    ParsedInstruction::CInstruction(instruction, span.clone())
+}
+
+fn symbol<'a>(name: &'static str, span: &Range<usize>) -> ParsedInstruction<'a> {
+    let value = AValue::Symbol {
+        name: name.into(),
+        span: span.clone(),
+    };
+    ParsedInstruction::AInstruction(AInstruction { value }, span.clone())
+}
+
+/// The shortest sequence of instructions that loads any 16 bit word into A or D.
+fn load_word<'a>(register: Register, word: u16, span: &Range<usize>) -> Vec<ParsedInstruction<'a>> {
+    match (register, word) {
+        (_, 0xFFFF) => vec![c(register, Computation::Literal(-1), span)],
+        (Register::D, 0 | 1) => vec![c(register, Computation::Literal(word as i8), span)],
+        (Register::A, word) if word <= HackInt::MAX => vec![a(word, span)],
+        (_, word) if word <= HackInt::MAX => {
+            vec![
+                a(word, span),
+                c(register, Computation::Identity(Register::A), span),
+            ]
+        }
+        // only -32768 cannot be negated, so it is loaded as !32767
+        (_, 0x8000) => vec![
+            a(!word, span),
+            c(register, Computation::Not(Register::A), span),
+        ],
+        (_, word) => vec![
+            a(word.wrapping_neg(), span),
+            c(register, Computation::Neg(Register::A), span),
+        ],
+    }
+}
+
+/// Expands `@@value` or `LDD value`, which load a value that does not fit into an
+/// a-instruction, such as a negative number or one with the highest bit set.
+fn load(load: Pair<Rule>) -> Result<Vec<ParsedInstruction>, AssemblerError> {
+    let span = range(&load.as_span());
+    let register = match load.as_rule() {
+        Rule::load_a => Register::A,
+        _ => Register::D,
+    };
+
+    let value = load.into_inner().next().unwrap();
+    let word = HackInt::parse_word(value.as_str())
+        .map_err(|e| AssemblerError::from(e).with_span(&value.as_span()))?;
+
+    Ok(load_word(register, word, &span))
+}
+
+/// Loads the address every remaining pseudo-instruction starts with.
+fn address<'a>(
+    pairs: &mut Pairs<'a, Rule>,
+    span: &Range<usize>,
+) -> Result<ParsedInstruction<'a>, AssemblerError> {
+    let pair = pairs.find(|p| p.as_rule() == Rule::expression).unwrap();
+    let (value, _) = expression(pair)?;
+
+    Ok(ParsedInstruction::AInstruction(
+        AInstruction { value },
+        span.clone(),
+    ))
+}
+
+fn condition(condition: &str) -> JumpType {
+    match condition {
+        ">" => JumpType::Jgt,
+        "=" | "==" => JumpType::Jeq,
+        "<" => JumpType::Jlt,
+        ">=" => JumpType::Jge,
+        "<=" => JumpType::Jle,
+        "!=" => JumpType::Jne,
+        _ => unreachable!(),
+    }
+}
+
+/// Lowers a pseudo-instruction into the real instructions it stands for.
+///
+/// Every expanded instruction keeps the span of the pseudo-instruction, so errors and
+/// listings point at the line that was written.
+pub fn pseudo(pseudo: Pair<Rule>) -> Result<Vec<ParsedInstruction>, AssemblerError> {
+    let span = range(&pseudo.as_span());
+    let inner = pseudo.into_inner().next().unwrap();
+    let rule = inner.as_rule();
+
+    if let Rule::load_a | Rule::load_d = rule {
+        return load(inner);
+    }
+
+    let mut pairs = inner.into_inner();
+
+    let instructions = match rule {
+        Rule::goto => vec![
+            address(&mut pairs, &span)?,
+            jump(Computation::Literal(0), JumpType::Jmp, &span),
+        ],
+        Rule::if_goto => {
+            let jump_type = condition(pairs.nth(1).unwrap().as_str());
+            vec![
+                address(&mut pairs, &span)?,
+                jump(Computation::Identity(Register::D), jump_type, &span),
+            ]
+        }
+        Rule::push => vec![
+            symbol("SP", &span),
+            c_multi(
+                &[Register::A, Register::M],
+                Computation::Inc(Register::M),
+                &span,
+            ),
+            c(Register::A, Computation::Dec(Register::A), &span),
+            c(Register::M, Computation::Identity(Register::D), &span),
+        ],
+        Rule::pop => vec![
+            symbol("SP", &span),
+            c_multi(
+                &[Register::A, Register::M],
+                Computation::Dec(Register::M),
+                &span,
+            ),
+            c(Register::D, Computation::Identity(Register::M), &span),
+        ],
+        Rule::increment => vec![
+            address(&mut pairs, &span)?,
+            c(Register::M, Computation::Inc(Register::M), &span),
+        ],
+        Rule::decrement => vec![
+            address(&mut pairs, &span)?,
+            c(Register::M, Computation::Dec(Register::M), &span),
+        ],
+        Rule::clear => vec![
+            address(&mut pairs, &span)?,
+            c(Register::M, Computation::Literal(0), &span),
+        ],
+        Rule::load_memory => vec![
+            address(&mut pairs, &span)?,
+            c(Register::D, Computation::Identity(Register::M), &span),
+        ],
+        Rule::store => vec![
+            address(&mut pairs, &span)?,
+            c(Register::M, Computation::Identity(Register::D), &span),
+        ],
+        _ => unreachable!(),
+    };
+
+    Ok(instructions)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        assembler_context::AssemblerErrorKind,
+        options::Dialect,
+        parsing::{parse_str, ParseError},
+        Assembler,
+    };
+
+    fn assemble(program: &str) -> Vec<u16> {
+        Assembler::new(program).assemble().unwrap()
+    }
+
+    #[test]
+    fn test_push_and_pop() {
+        // @SP AM=M+1 A=A-1 M=D, @SP AM=M-1 D=M
+        assert_eq!(
+            assemble("push D\npop D\n"),
+            [0, 0xFDE8, 0xECA0, 0xE308, 0, 0xFCA8, 0xFC10]
+        );
+    }
+
+    #[test]
+    fn test_goto() {
+        assert_eq!(assemble("(LOOP)\ngoto LOOP\n"), [0, 0xEA87]);
+        assert_eq!(
+            assemble("(LOOP)\nif D >= 0 goto LOOP\nif D!=0 goto LOOP\n"),
+            [0, 0xE303, 0, 0xE305]
+        );
+    }
+
+    #[test]
+    fn test_memory() {
+        // @x M=M+1, @x M=M-1, @x M=0, @x D=M, @x M=D
+        assert_eq!(
+            assemble("inc x\ndec x\nclear x\nmov D, x\nmov x, D\n"),
+            [16, 0xFDC8, 16, 0xFC88, 16, 0xEA88, 16, 0xFC10, 16, 0xE308]
+        );
+    }
+
+    #[test]
+    fn test_load_negative_constant() {
+        // @5 D=-A
+        assert_eq!(assemble("LDD -5\n"), [5, 0xECD0]);
+    }
+
+    #[test]
+    fn test_load_lowest_constant() {
+        // -32768 cannot be negated: @32767 A=!A
+        assert_eq!(assemble("@@0x8000\n"), [32767, 0xEC60]);
+    }
+
+    #[test]
+    fn test_load_shortest_sequence() {
+        // D=-1, D=1, @100 D=A, @100
+        assert_eq!(
+            assemble("LDD 0xFFFF\nLDD 1\nLDD 100\n@@100\n"),
+            [0xEE90, 0xEFD0, 100, 0xEC10, 100]
+        );
+    }
+
+    #[test]
+    fn test_labels_after_a_load() {
+        assert_eq!(assemble("LDD -5\n(END)\n@END\n"), [5, 0xECD0, 2]);
+    }
+
+    #[test]
+    fn test_keywords_end_with_the_word() {
+        for line in [
+            "incx", "decx", "pushD", "popD", "gotoLOOP", "clearx", "movD, x",
+        ] {
+            let output = parse_str(line, Dialect::Permissive);
+            assert!(output.pseudo_instructions.is_empty(), "{}", line);
+            assert!(
+                matches!(
+                    &output.errors[..],
+                    [e] if matches!(e.kind, AssemblerErrorKind::ParseError(ParseError::Syntax(_)))
+                ),
+                "{}",
+                line
+            );
+        }
+
+        let output = parse_str("if D >= 0 gotoLOOP", Dialect::Permissive);
+        assert_eq!(output.errors.len(), 1);
+    }
+
+    #[test]
+    fn test_symbols_starting_with_a_keyword() {
+        // @popcount D=M, @increment M=M+1
+        assert_eq!(
+            assemble("mov D, popcount\ninc increment\n"),
+            [16, 0xFC10, 17, 0xFDC8]
+        );
+    }
+
+    #[test]
+    fn test_without_pseudo_instructions() {
+        let error = Assembler::new("@1\ngoto LOOP\n(LOOP)\n")
+            .without_pseudo_instructions()
+            .assemble()
+            .unwrap_err();
+        assert!(
+            matches!(error.kind, AssemblerErrorKind::PseudoInstruction(name) if name == "goto")
+        );
+    }
+}
@@ -0,0 +1,325 @@
+use thiserror::Error;
+
+use crate::{cpu::Cpu, output::Endianness};
+
+#[derive(Error, Debug)]
+pub enum RomError {
+    #[error("line {line}: \"{word}\" is not a 16 bit binary word")]
+    InvalidWord { line: usize, word: String },
+    #[error(
+        "a ROM image has to hold between 1 and {} words, not {0}",
+        crate::constants::ROM_SIZE
+    )]
+    InvalidDepth(usize),
+    #[error("the program has {words} words, but the ROM image only holds {depth}")]
+    ProgramTooLarge { words: usize, depth: usize },
+    #[error("line {line}: {reason}")]
+    InvalidRecord { line: usize, reason: &'static str },
+    #[error("line {line}: \"{word}\" is not a 16 bit hexadecimal word")]
+    InvalidHexWord { line: usize, word: String },
+    #[error("a raw binary image has to consist of whole 16 bit words, but it has {0} bytes")]
+    OddLength(usize),
+    #[error("not a Logisim image, expected \"v2.0 raw\" in the first line")]
+    MissingLogisimHeader,
+}
+
+/// Reads the words of a .hack file, one 16 digit binary number per line.
+/// Empty lines are skipped.
+pub fn read_hack(input: &str) -> Result<Vec<u16>, RomError> {
+    let mut program = Vec::new();
+
+    for (index, line) in input.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        match u16::from_str_radix(line, 2) {
+            Ok(word) if line.len() == 16 => program.push(word),
+            _ => {
+                return Err(RomError::InvalidWord {
+                    line: index + 1,
+                    word: line.to_string(),
+                })
+            }
+        }
+    }
+
+    Ok(program)
+}
+
+/// A format [`HackRom`] can read a program from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RomFormat {
+    /// One 16 digit binary number per line.
+    Hack,
+    /// Two bytes per word without any separators.
+    Binary(Endianness),
+    /// Intel HEX with word addresses, as written by [`crate::IntelHex`].
+    IntelHex,
+    /// A `v2.0 raw` image of Logisim or Digital.
+    Logisim,
+}
+
+impl RomFormat {
+    /// Guesses the format from the content of a file.
+    ///
+    /// Text starting with `v2.0 raw` is a Logisim image, text starting with `:` is Intel HEX
+    /// and text whose lines are all 16 binary digits is a .hack file. Everything else is
+    /// a big-endian raw binary image, even if its bytes happen to be printable.
+    pub fn detect(bytes: &[u8]) -> Self {
+        let text = std::str::from_utf8(bytes)
+            .ok()
+            .filter(|text| !text.contains(|c: char| c.is_control() && !c.is_whitespace()));
+
+        match text.map(str::trim_start) {
+            Some(text) if text.starts_with("v2.0 raw") => RomFormat::Logisim,
+            Some(text) if text.starts_with(':') => RomFormat::IntelHex,
+            Some(text) if text.lines().all(is_hack_line) => RomFormat::Hack,
+            _ => RomFormat::Binary(Endianness::Big),
+        }
+    }
+}
+
+/// Whether a line is empty or a word of a .hack file.
+fn is_hack_line(line: &str) -> bool {
+    let line = line.trim();
+    line.is_empty() || line.len() == 16 && line.bytes().all(|b| b == b'0' || b == b'1')
+}
+
+/// The words of a program that was read from a ROM image.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HackRom {
+    pub words: Vec<u16>,
+}
+
+impl HackRom {
+    /// Reads a ROM image in the format [`RomFormat::detect`] finds.
+    pub fn parse(bytes: &[u8]) -> Result<Self, RomError> {
+        Self::parse_as(bytes, RomFormat::detect(bytes))
+    }
+
+    /// Reads a ROM image in the given format.
+    /// Errors in text formats name the line they were found in.
+    pub fn parse_as(bytes: &[u8], format: RomFormat) -> Result<Self, RomError> {
+        // text formats are read lossily, so invalid bytes are reported as part of a word
+        let text = || String::from_utf8_lossy(bytes);
+
+        let words = match format {
+            RomFormat::Hack => read_hack(&text())?,
+            RomFormat::Binary(endianness) => read_binary(bytes, endianness)?,
+            RomFormat::IntelHex => read_intel_hex(&text())?,
+            RomFormat::Logisim => read_logisim(&text())?,
+        };
+
+        if words.len() > Cpu::ROM_SIZE {
+            return Err(RomError::ProgramTooLarge {
+                words: words.len(),
+                depth: Cpu::ROM_SIZE,
+            });
+        }
+
+        Ok(HackRom { words })
+    }
+}
+
+fn read_binary(bytes: &[u8], endianness: Endianness) -> Result<Vec<u16>, RomError> {
+    if !bytes.len().is_multiple_of(2) {
+        return Err(RomError::OddLength(bytes.len()));
+    }
+
+    let words = bytes
+        .chunks_exact(2)
+        .map(|pair| match endianness {
+            Endianness::Big => u16::from_be_bytes([pair[0], pair[1]]),
+            Endianness::Little => u16::from_le_bytes([pair[0], pair[1]]),
+        })
+        .collect();
+
+    Ok(words)
+}
+
+fn read_intel_hex(input: &str) -> Result<Vec<u16>, RomError> {
+    let mut words = Vec::new();
+    // set by extended address records, in words like every other address
+    let mut base = 0;
+
+    for (index, line) in input.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let invalid = |reason| RomError::InvalidRecord {
+            line: index + 1,
+            reason,
+        };
+
+        let digits = line
+            .strip_prefix(':')
+            .ok_or_else(|| invalid("a record has to start with ':'"))?;
+        if !digits.len().is_multiple_of(2) || !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(invalid(
+                "a record has to consist of pairs of hexadecimal digits",
+            ));
+        }
+
+        let bytes: Vec<u8> = (0..digits.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).unwrap())
+            .collect();
+        if bytes.len() < 5 || bytes.len() != bytes[0] as usize + 5 {
+            return Err(invalid(
+                "the length of the record does not match its byte count",
+            ));
+        }
+        if bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) != 0 {
+            return Err(invalid("checksum mismatch"));
+        }
+
+        let address = u16::from_be_bytes([bytes[1], bytes[2]]) as usize;
+        let data = &bytes[4..bytes.len() - 1];
+
+        match bytes[3] {
+            0x00 => {
+                if !data.len().is_multiple_of(2) {
+                    return Err(invalid("a data record has to hold whole 16 bit words"));
+                }
+
+                let start = base + address;
+                let end = start + data.len() / 2;
+                if end > Cpu::ROM_SIZE {
+                    return Err(invalid("the record does not fit into the ROM"));
+                }
+                if words.len() < end {
+                    words.resize(end, 0);
+                }
+
+                for (word, pair) in words[start..end].iter_mut().zip(data.chunks_exact(2)) {
+                    *word = u16::from_be_bytes([pair[0], pair[1]]);
+                }
+            }
+            0x01 => break,
+            0x02 | 0x04 if data.len() == 2 => {
+                let value = u16::from_be_bytes([data[0], data[1]]) as usize;
+                base = if bytes[3] == 0x02 {
+                    value << 4
+                } else {
+                    value << 16
+                };
+            }
+            // start addresses do not matter for a ROM
+            0x03 | 0x05 => (),
+            _ => return Err(invalid("unsupported record type")),
+        }
+    }
+
+    Ok(words)
+}
+
+fn read_logisim(input: &str) -> Result<Vec<u16>, RomError> {
+    let mut lines = input.lines().enumerate();
+    if lines.next().map(|(_, line)| line.trim()) != Some("v2.0 raw") {
+        return Err(RomError::MissingLogisimHeader);
+    }
+
+    let mut words = Vec::new();
+
+    for (index, line) in lines {
+        let line = line.split('#').next().unwrap_or("");
+
+        for entry in line.split_whitespace() {
+            let invalid = || RomError::InvalidHexWord {
+                line: index + 1,
+                word: entry.to_string(),
+            };
+
+            // runs of the same word are written as COUNT*WORD
+            let (count, word) = match entry.split_once('*') {
+                Some((count, word)) => (count.parse().map_err(|_| invalid())?, word),
+                None => (1, entry),
+            };
+            let word = u16::from_str_radix(word, 16).map_err(|_| invalid())?;
+
+            if words.len() + count > Cpu::ROM_SIZE {
+                return Err(RomError::ProgramTooLarge {
+                    words: words.len() + count,
+                    depth: Cpu::ROM_SIZE,
+                });
+            }
+            words.resize(words.len() + count, word);
+        }
+    }
+
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        assembler::AssembledProgram,
+        output::{Hack, OutputFormat, RawBinary},
+        rom_image::{IntelHex, Logisim},
+    };
+
+    const WORDS: [u16; 5] = [0x0010, 0xEE90, 0, 0, 0xEA87];
+
+    fn program() -> AssembledProgram {
+        AssembledProgram::from(WORDS.to_vec())
+    }
+
+    #[test]
+    fn test_read_hack() {
+        let rom = HackRom::parse(&Hack.to_bytes(&program())).unwrap();
+        assert_eq!(rom.words, WORDS);
+    }
+
+    #[test]
+    fn test_read_big_endian_binary() {
+        let rom = HackRom::parse(&RawBinary(Endianness::Big).to_bytes(&program())).unwrap();
+        assert_eq!(rom.words, WORDS);
+    }
+
+    #[test]
+    fn test_read_little_endian_binary() {
+        let little = RawBinary(Endianness::Little).to_bytes(&program());
+        let rom = HackRom::parse_as(&little, RomFormat::Binary(Endianness::Little)).unwrap();
+        assert_eq!(rom.words, WORDS);
+    }
+
+    #[test]
+    fn test_read_intel_hex() {
+        let rom = HackRom::parse(&IntelHex::default().to_bytes(&program())).unwrap();
+        assert_eq!(rom.words, WORDS);
+
+        let error = HackRom::parse(b":020000000010EE\n:02000100EE9080\n").unwrap_err();
+        assert!(matches!(error, RomError::InvalidRecord { line: 2, .. }));
+    }
+
+    #[test]
+    fn test_read_logisim() {
+        let rom = HackRom::parse(&Logisim::default().to_bytes(&program())).unwrap();
+        assert_eq!(rom.words, WORDS);
+
+        let error = HackRom::parse(b"v2.0 raw\n10 ee90\n3*0 ea8g\n").unwrap_err();
+        assert!(matches!(error, RomError::InvalidHexWord { line: 3, .. }));
+    }
+
+    #[test]
+    fn test_detect_printable_binary() {
+        let bytes = b"AB\ncd\n";
+        assert_eq!(RomFormat::detect(bytes), RomFormat::Binary(Endianness::Big));
+        assert_eq!(
+            HackRom::parse(bytes).unwrap().words,
+            [0x4142, 0x0A63, 0x640A]
+        );
+    }
+
+    #[test]
+    fn test_detect_hack() {
+        let text = b"0000000000010000\r\n\n1110111010010000\n";
+        assert_eq!(RomFormat::detect(text), RomFormat::Hack);
+        assert_eq!(HackRom::parse(text).unwrap().words, [0x0010, 0xEE90]);
+    }
+}
@@ -0,0 +1,354 @@
+use std::{
+    borrow::Cow,
+    io::{self, Write},
+};
+
+use crate::{
+    assembler::AssembledProgram, constants::ROM_SIZE, output::OutputFormat, rom::RomError,
+};
+
+/// How many words a ROM image holds.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Depth {
+    /// As many words as the program has, but at least one, since an empty memory is invalid.
+    #[default]
+    Program,
+    /// Pads the program with zeros, a program that does not fit is an error.
+    Pad(usize),
+    /// Pads the program with zeros or cuts off the words that do not fit.
+    Truncate(usize),
+}
+
+/// Brings the program to the size of the image, between 1 and [`ROM_SIZE`] words.
+fn fit(words: &[u16], depth: Depth) -> io::Result<Cow<'_, [u16]>> {
+    let invalid = |e: RomError| io::Error::new(io::ErrorKind::InvalidInput, e);
+
+    let depth = match depth {
+        Depth::Pad(depth) | Depth::Truncate(depth) if depth == 0 || depth > ROM_SIZE => {
+            return Err(invalid(RomError::InvalidDepth(depth)))
+        }
+        Depth::Pad(depth) if words.len() > depth => {
+            return Err(invalid(RomError::ProgramTooLarge {
+                words: words.len(),
+                depth,
+            }))
+        }
+        Depth::Pad(depth) | Depth::Truncate(depth) => depth,
+        Depth::Program if words.len() > ROM_SIZE => {
+            return Err(invalid(RomError::ProgramTooLarge {
+                words: words.len(),
+                depth: ROM_SIZE,
+            }))
+        }
+        Depth::Program if words.is_empty() => 1,
+        Depth::Program => return Ok(Cow::Borrowed(words)),
+    };
+
+    let mut words = words[..words.len().min(depth)].to_vec();
+    words.resize(depth, 0);
+    Ok(Cow::Owned(words))
+}
+
+/// An Intel HEX file as read by Quartus and most EPROM programmers.
+///
+/// Addresses count 16 bit words, every word is stored big-endian
+/// and every data record holds up to 8 words.
+#[derive(Default)]
+pub struct IntelHex {
+    pub depth: Depth,
+}
+
+/// The number base of a [`Verilog`] memory file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Radix {
+    /// For `$readmemb`.
+    Binary,
+    /// For `$readmemh`.
+    Hex,
+}
+
+/// A memory file for the `$readmemb` or `$readmemh` task of Verilog, one word per line.
+pub struct Verilog {
+    pub radix: Radix,
+    pub depth: Depth,
+}
+
+/// An Altera/Intel memory initialization file (.mif).
+#[derive(Default)]
+pub struct Mif {
+    pub depth: Depth,
+}
+
+/// A Xilinx coefficient file (.coe) for block memory generators.
+#[derive(Default)]
+pub struct Coe {
+    pub depth: Depth,
+}
+
+/// A VHDL package with the program as a constant array named `ROM`.
+#[derive(Default)]
+pub struct Vhdl {
+    pub depth: Depth,
+}
+
+/// A `v2.0 raw` image that the ROM components of Logisim and Digital can load.
+#[derive(Default)]
+pub struct Logisim {
+    pub depth: Depth,
+}
+
+impl OutputFormat for IntelHex {
+    fn extension(&self) -> &'static str {
+        "ihx"
+    }
+
+    fn write(&self, program: &AssembledProgram, output: &mut dyn Write) -> io::Result<()> {
+        let words = fit(&program.words, self.depth)?;
+
+        for (index, record) in words.chunks(8).enumerate() {
+            let address = (index * 8) as u16;
+            let mut bytes = vec![(record.len() * 2) as u8];
+            bytes.extend(address.to_be_bytes());
+            bytes.push(0x00);
+            bytes.extend(record.iter().flat_map(|word| word.to_be_bytes()));
+
+            let checksum = bytes
+                .iter()
+                .fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+                .wrapping_neg();
+
+            write!(output, ":")?;
+            for byte in bytes.iter().chain([&checksum]) {
+                write!(output, "{:02X}", byte)?;
+            }
+            writeln!(output)?;
+        }
+
+        writeln!(output, ":00000001FF")
+    }
+}
+
+impl OutputFormat for Verilog {
+    fn extension(&self) -> &'static str {
+        "mem"
+    }
+
+    fn write(&self, program: &AssembledProgram, output: &mut dyn Write) -> io::Result<()> {
+        for word in fit(&program.words, self.depth)?.iter() {
+            match self.radix {
+                Radix::Binary => writeln!(output, "{:016b}", word)?,
+                Radix::Hex => writeln!(output, "{:04x}", word)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl OutputFormat for Mif {
+    fn extension(&self) -> &'static str {
+        "mif"
+    }
+
+    fn write(&self, program: &AssembledProgram, output: &mut dyn Write) -> io::Result<()> {
+        let words = fit(&program.words, self.depth)?;
+
+        writeln!(output, "DEPTH = {};", words.len())?;
+        writeln!(output, "WIDTH = 16;")?;
+        writeln!(output, "ADDRESS_RADIX = HEX;")?;
+        writeln!(output, "DATA_RADIX = BIN;")?;
+        writeln!(output, "CONTENT")?;
+        writeln!(output, "BEGIN")?;
+        for (address, word) in words.iter().enumerate() {
+            writeln!(output, "{:04X} : {:016b};", address, word)?;
+        }
+        writeln!(output, "END;")
+    }
+}
+
+impl OutputFormat for Coe {
+    fn extension(&self) -> &'static str {
+        "coe"
+    }
+
+    fn write(&self, program: &AssembledProgram, output: &mut dyn Write) -> io::Result<()> {
+        let words = fit(&program.words, self.depth)?;
+
+        writeln!(output, "memory_initialization_radix=2;")?;
+        write!(output, "memory_initialization_vector=")?;
+        for (index, word) in words.iter().enumerate() {
+            let separator = if index == 0 { "" } else { "," };
+            write!(output, "{}\n{:016b}", separator, word)?;
+        }
+        writeln!(output, ";")
+    }
+}
+
+impl OutputFormat for Vhdl {
+    fn extension(&self) -> &'static str {
+        "vhd"
+    }
+
+    fn write(&self, program: &AssembledProgram, output: &mut dyn Write) -> io::Result<()> {
+        let words = fit(&program.words, self.depth)?;
+        // trailing zeros are covered by `others`
+        let used = words.iter().rposition(|&w| w != 0).map_or(0, |i| i + 1);
+
+        writeln!(output, "library ieee;")?;
+        writeln!(output, "use ieee.std_logic_1164.all;")?;
+        writeln!(output)?;
+        writeln!(output, "package hack_rom is")?;
+        writeln!(
+            output,
+            "    type rom_type is array (0 to {}) of std_logic_vector(15 downto 0);",
+            words.len() - 1
+        )?;
+        writeln!(output, "    constant ROM : rom_type := (")?;
+        for (address, word) in words[..used].iter().enumerate() {
+            writeln!(output, "        {} => \"{:016b}\",", address, word)?;
+        }
+        writeln!(output, "        others => (others => '0')")?;
+        writeln!(output, "    );")?;
+        writeln!(output, "end package;")
+    }
+}
+
+impl OutputFormat for Logisim {
+    fn extension(&self) -> &'static str {
+        "rom"
+    }
+
+    fn write(&self, program: &AssembledProgram, output: &mut dyn Write) -> io::Result<()> {
+        let words = fit(&program.words, self.depth)?;
+        let used = words.iter().rposition(|&w| w != 0).map_or(0, |i| i + 1);
+
+        writeln!(output, "v2.0 raw")?;
+        for line in words[..used].chunks(8) {
+            let line: Vec<_> = line.iter().map(|word| format!("{:x}", word)).collect();
+            writeln!(output, "{}", line.join(" "))?;
+        }
+        // the padding is written as a run-length entry instead of one zero per word
+        if words.len() > used {
+            writeln!(output, "{}*0", words.len() - used)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(format: &dyn OutputFormat, words: &[u16]) -> String {
+        let program = AssembledProgram::from(words.to_vec());
+        String::from_utf8(format.to_bytes(&program)).unwrap()
+    }
+
+    const PROGRAM: [u16; 2] = [0x0010, 0xEE90];
+
+    #[test]
+    fn test_intel_hex() {
+        assert_eq!(
+            text(&IntelHex::default(), &PROGRAM),
+            ":040000000010EE906E\n:00000001FF\n"
+        );
+    }
+
+    #[test]
+    fn test_verilog() {
+        let verilog = Verilog {
+            radix: Radix::Hex,
+            depth: Depth::Pad(3),
+        };
+        assert_eq!(text(&verilog, &PROGRAM), "0010\nee90\n0000\n");
+    }
+
+    #[test]
+    fn test_coe() {
+        assert_eq!(
+            text(&Coe::default(), &PROGRAM),
+            "memory_initialization_radix=2;\nmemory_initialization_vector=\n0000000000010000,\n1110111010010000;\n"
+        );
+    }
+
+    #[test]
+    fn test_mif() {
+        let mif = text(
+            &Mif {
+                depth: Depth::Pad(4),
+            },
+            &PROGRAM,
+        );
+        assert!(mif.contains("DEPTH = 4;\n"));
+    }
+
+    #[test]
+    fn test_vhdl() {
+        let vhdl = text(
+            &Vhdl {
+                depth: Depth::Pad(4),
+            },
+            &PROGRAM,
+        );
+        assert!(vhdl.contains("array (0 to 3)"));
+    }
+
+    #[test]
+    fn test_logisim() {
+        let logisim = Logisim {
+            depth: Depth::Pad(10),
+        };
+        assert_eq!(text(&logisim, &PROGRAM), "v2.0 raw\n10 ee90\n8*0\n");
+    }
+
+    #[test]
+    fn test_program_too_large() {
+        let program = AssembledProgram::from(PROGRAM.to_vec());
+        let mut output = Vec::new();
+        let error = Mif {
+            depth: Depth::Pad(1),
+        }
+        .write(&program, &mut output)
+        .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_truncate() {
+        let verilog = Verilog {
+            radix: Radix::Hex,
+            depth: Depth::Truncate(1),
+        };
+        assert_eq!(text(&verilog, &PROGRAM), "0010\n");
+
+        let verilog = Verilog {
+            radix: Radix::Hex,
+            depth: Depth::Truncate(3),
+        };
+        assert_eq!(text(&verilog, &PROGRAM), "0010\nee90\n0000\n");
+    }
+
+    #[test]
+    fn test_invalid_depth() {
+        let program = AssembledProgram::from(PROGRAM.to_vec());
+        let mut output = Vec::new();
+
+        for depth in [
+            Depth::Pad(0),
+            Depth::Truncate(0),
+            Depth::Pad(ROM_SIZE + 1),
+            Depth::Truncate(ROM_SIZE + 1),
+        ] {
+            assert!(Mif { depth }.write(&program, &mut output).is_err());
+        }
+    }
+
+    #[test]
+    fn test_empty_program() {
+        let mif = text(&Mif::default(), &[]);
+        assert!(mif.contains("DEPTH = 1;\n"));
+        assert!(mif.contains("BEGIN\n0000 : 0000000000000000;\nEND;\n"));
+        assert!(text(&Vhdl::default(), &[]).contains("array (0 to 0)"));
+    }
+}
@@ -1,5 +1,5 @@
 use phf::phf_map;
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt};
 use thiserror::Error;
 
 use crate::hack_int::HackInt;
@@ -18,7 +18,33 @@ pub enum SymbolTableSetError {
     Redefined(String),
 }
 
-static BUILT_IN: phf::Map<&'static str, HackInt> = phf_map! {
+/// How a user defined symbol got its value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SymbolKind {
+    Label,
+    Constant,
+    Variable,
+}
+
+impl fmt::Display for SymbolKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SymbolKind::Label => write!(f, "label"),
+            SymbolKind::Constant => write!(f, "constant"),
+            SymbolKind::Variable => write!(f, "variable"),
+        }
+    }
+}
+
+/// A user defined symbol together with its value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub value: u16,
+    pub kind: SymbolKind,
+}
+
+pub(crate) static BUILT_IN: phf::Map<&'static str, HackInt> = phf_map! {
     // Virtual Registers
     "R0" =>  HackInt::new_unchecked(0),
     "R1" =>  HackInt::new_unchecked(1),
@@ -47,7 +73,7 @@ static BUILT_IN: phf::Map<&'static str, HackInt> = phf_map! {
     "THAT" => HackInt::new_unchecked(4),
 };
 
-/// The symbol table stores and resolves symbols (labels and variables)
+/// The symbol table stores and resolves symbols (labels, constants and variables)
 /// to their associated addresses or values.
 ///
 /// # List of predefined symbols
@@ -82,11 +108,12 @@ static BUILT_IN: phf::Map<&'static str, HackInt> = phf_map! {
 /// ------------------------
 /// # Example
 /// ## Basic Usage
-/// ```ignore
-/// # use hack_asm::SymbolTable;
+/// ```
+/// # use hack_asm::{HackInt, SymbolKind, SymbolTable};
 /// let mut table = SymbolTable::new();
-/// table.set("value", 42).unwrap();
-/// assert!(table.set("value", 101).is_err());      // a symbols' value may only be set once
+/// let value = HackInt::try_new(42).unwrap();
+/// table.set("value", value, SymbolKind::Variable).unwrap();
+/// assert!(table.set("value", value, SymbolKind::Variable).is_err());  // a symbols' value may only be set once
 ///
 /// assert_eq!(table.get("value").unwrap(), 42);    // defined symbol
 /// assert!(table.get("undefined").is_err());       // undefined symbol
@@ -96,64 +123,107 @@ static BUILT_IN: phf::Map<&'static str, HackInt> = phf_map! {
 /// ## Predefined Symbols
 /// By design of the Hack assembly language we already have predefined symbols inside
 /// of our symbol table.
-/// ```ignore
-/// # use hack_asm::SymbolTable;
+/// ```
+/// # use hack_asm::{HackInt, SymbolKind, SymbolTable};
 /// let mut table = SymbolTable::new();
+/// let value = HackInt::try_new(101).unwrap();
 ///
-/// assert!(table.set("R10", 101).is_err());           // built in symbol cannot be redefined
+/// assert!(table.set("R10", value, SymbolKind::Variable).is_err());  // built in symbol cannot be redefined
 /// assert_eq!(table.get("SCREEN").unwrap(), 16384);   // predefined
 /// assert_eq!(table.get("R10").unwrap(), 10);         // predefined
 /// ```
 pub struct SymbolTable {
-    table: HashMap<String, HackInt>,
+    table: HashMap<String, (HackInt, SymbolKind)>,
+    built_in: HashMap<String, HackInt>,
 }
 
 impl SymbolTable {
     pub fn new() -> Self {
         Self {
             table: HashMap::default(),
+            built_in: BUILT_IN
+                .entries()
+                .map(|(&name, &value)| (name.to_string(), value))
+                .collect(),
         }
     }
 
+    /// Adds a built in symbol or changes the value of an existing one.
+    pub fn define_built_in(&mut self, name: &str, value: HackInt) {
+        self.built_in.insert(name.to_string(), value);
+    }
+
+    /// Removes a built in symbol, so it can be defined like any other symbol.
+    pub fn remove_built_in(&mut self, name: &str) {
+        self.built_in.remove(name);
+    }
+
+    pub fn is_built_in(&self, name: &str) -> bool {
+        self.built_in.contains_key(name)
+    }
+
     /// Sets the value of a symbol to the specified value.
     /// This function should also create the symbol if it does not exist.
     /// Overwriting a built in or an already defined symbol is not allowed.
-    /// May return a [`SymbolTableError`]
+    /// May return a [`SymbolTableSetError`]
     /// # Arguments
     /// * `name` - A string that contains the name of the symbol
     /// * `value` - The value (or address) associated with the symbol
-    pub fn set(&mut self, name: &str, value: HackInt) -> Result<(), SymbolTableSetError> {
-        if BUILT_IN.get(name).is_some() {
+    /// * `kind` - Whether the symbol is a label, a constant or a variable
+    pub fn set(
+        &mut self,
+        name: &str,
+        value: HackInt,
+        kind: SymbolKind,
+    ) -> Result<(), SymbolTableSetError> {
+        if self.is_built_in(name) {
             return Err(SymbolTableSetError::RedefinedBuiltIn(name.to_string()));
         }
 
-        let entry = self.table.raw_entry_mut().from_key(name);
-        match entry {
-            std::collections::hash_map::RawEntryMut::Occupied(_) => {
-                Err(SymbolTableSetError::Redefined(name.to_string()))
-            }
-            std::collections::hash_map::RawEntryMut::Vacant(entry) => {
-                entry.insert(name.to_string(), value);
-                Ok(())
-            }
+        if self.table.contains_key(name) {
+            return Err(SymbolTableSetError::Redefined(name.to_string()));
         }
+
+        self.table.insert(name.to_string(), (value, kind));
+        Ok(())
     }
 
     /// Retrieves the value of a symbol.
-    /// May return a [`SymbolTableError`]
+    /// May return a [`SymbolTableGetError`]
     /// # Arguments
     /// * `name` - The symbol name to look up
     pub fn get(&self, name: &str) -> Result<HackInt, SymbolTableGetError> {
-        if let Some(&built_in) = BUILT_IN.get(name) {
+        if let Some(&built_in) = self.built_in.get(name) {
             return Ok(built_in);
         }
 
-        if let Some(&user_defined) = self.table.get(name) {
+        if let Some(&(user_defined, _)) = self.table.get(name) {
             return Ok(user_defined);
         }
 
         Err(SymbolTableGetError::NotDefined(name.to_string()))
     }
+
+    /// The kind of a user defined symbol, `None` for built in and undefined symbols.
+    pub fn kind(&self, name: &str) -> Option<SymbolKind> {
+        self.table.get(name).map(|&(_, kind)| kind)
+    }
+
+    /// Lists every user defined symbol, ordered by kind and value.
+    pub fn symbols(&self) -> Vec<Symbol> {
+        let mut symbols: Vec<_> = self
+            .table
+            .iter()
+            .map(|(name, &(value, kind))| Symbol {
+                name: name.clone(),
+                value: value.into(),
+                kind,
+            })
+            .collect();
+
+        symbols.sort_by(|a, b| (a.kind, a.value, &a.name).cmp(&(b.kind, b.value, &b.name)));
+        symbols
+    }
 }
 
 impl Default for SymbolTable {
@@ -171,7 +241,9 @@ mod tests {
         let mut table = SymbolTable::new();
 
         // try to redefine a built in symbol
-        let error = table.set("R1", HackInt::new_unchecked(42)).unwrap_err();
+        let error = table
+            .set("R1", HackInt::new_unchecked(42), SymbolKind::Variable)
+            .unwrap_err();
 
         match error {
             SymbolTableSetError::RedefinedBuiltIn(_) => (),
@@ -184,9 +256,11 @@ mod tests {
         let mut table = SymbolTable::new();
 
         // try to redefine a user defined symbol
-        table.set("some_var", HackInt::new_unchecked(42)).unwrap();
+        table
+            .set("some_var", HackInt::new_unchecked(42), SymbolKind::Variable)
+            .unwrap();
         let error = table
-            .set("some_var", HackInt::new_unchecked(42))
+            .set("some_var", HackInt::new_unchecked(42), SymbolKind::Variable)
             .unwrap_err();
 
         match error {
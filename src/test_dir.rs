@@ -0,0 +1,32 @@
+use std::path::{Path, PathBuf};
+
+/// A temporary directory of a single test, which is removed once the test is done.
+pub(crate) struct TestDir(PathBuf);
+
+impl TestDir {
+    /// Creates an empty directory, `name` has to be unique among the tests.
+    pub(crate) fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("hack_asm_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// Writes a file relative to the directory and returns its full path.
+    pub(crate) fn write(&self, file: &str, contents: &str) -> String {
+        let path = self.0.join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, contents).unwrap();
+        path.display().to_string()
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
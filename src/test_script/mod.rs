@@ -0,0 +1,102 @@
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+use crate::{assembler_context::AssemblerError, cpu::CpuError, rom::RomError};
+
+mod parser;
+mod runner;
+
+pub use parser::parse_test_script;
+pub use runner::{run_test_script, TestReport, TestRunner};
+
+/// A variable of the CPU emulator that can be set or written to the output file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Variable {
+    A,
+    D,
+    Pc,
+    Ram(usize),
+    Time,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Binary,
+    Hex,
+    Decimal,
+    String,
+}
+
+/// A single column of an `output-list` such as `RAM[0]%D2.6.2`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    pub name: String,
+    pub variable: Variable,
+    pub format: Format,
+    pub left: usize,
+    pub width: usize,
+    pub right: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    pub variable: Variable,
+    pub comparison: Comparison,
+    pub value: i16,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Load(Option<String>),
+    OutputFile(String),
+    CompareTo(String),
+    OutputList(Vec<Column>),
+    Output,
+    Set(Variable, i16),
+    TickTock,
+    Tick,
+    Tock,
+    Echo(String),
+    ClearEcho,
+    Repeat(usize, Vec<Command>),
+    While(Condition, Vec<Command>),
+}
+
+#[derive(Error, Debug)]
+pub enum TestScriptError {
+    #[error("{0}")]
+    Syntax(String),
+    #[error("unknown variable \"{0}\"")]
+    UnknownVariable(String),
+    #[error("value {0} does not fit into 16 bits")]
+    ValueOutOfRange(String),
+    #[error("error reading {}: {}", .0.display(), .1)]
+    Io(PathBuf, std::io::Error),
+    #[error("{}: {}", .0.display(), .1)]
+    Rom(PathBuf, RomError),
+    #[error(transparent)]
+    Assembler(#[from] AssemblerError),
+    #[error(transparent)]
+    Cpu(#[from] CpuError),
+    #[error("no program has been loaded")]
+    NoProgram,
+    #[error("no output-list has been set")]
+    NoOutputList,
+    #[error("comparison failure at line {line}\nexpected: {expected}\nactual:   {actual}")]
+    ComparisonFailure {
+        line: usize,
+        expected: String,
+        actual: String,
+    },
+}
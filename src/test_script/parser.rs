@@ -0,0 +1,151 @@
+use pest::{iterators::Pair, Parser};
+
+use super::{Column, Command, Comparison, Condition, Format, TestScriptError, Variable};
+
+#[derive(Parser)]
+#[grammar = "grammar/tst.pest"]
+pub struct TestScriptParser;
+
+fn variable(variable: Pair<Rule>) -> Result<Variable, TestScriptError> {
+    let text = variable.as_str();
+    let mut parts = variable.into_inner();
+    let name = parts.next().unwrap().as_str();
+    let index = parts.next();
+
+    match (name, index) {
+        ("A", None) => Ok(Variable::A),
+        ("D", None) => Ok(Variable::D),
+        ("PC", None) => Ok(Variable::Pc),
+        ("time", None) => Ok(Variable::Time),
+        ("RAM" | "RAM16K" | "RAM32K", Some(index)) => match index.as_str().parse() {
+            Ok(address) if address < crate::cpu::Cpu::RAM_SIZE => Ok(Variable::Ram(address)),
+            _ => Err(TestScriptError::UnknownVariable(text.to_string())),
+        },
+        _ => Err(TestScriptError::UnknownVariable(text.to_string())),
+    }
+}
+
+fn value(value: Pair<Rule>) -> Result<i16, TestScriptError> {
+    let value = value.into_inner().next().unwrap();
+    let text = value.as_str();
+
+    let parsed = match value.as_rule() {
+        Rule::hex => u16::from_str_radix(&text[2..], 16).map(|v| v as i16),
+        Rule::bin => u16::from_str_radix(&text[2..], 2).map(|v| v as i16),
+        Rule::dec => text.trim_start_matches("%D").parse(),
+        _ => unreachable!(),
+    };
+
+    parsed.map_err(|_| TestScriptError::ValueOutOfRange(text.to_string()))
+}
+
+fn count(count: Pair<Rule>) -> Result<usize, TestScriptError> {
+    count
+        .as_str()
+        .parse()
+        .map_err(|_| TestScriptError::ValueOutOfRange(count.as_str().to_string()))
+}
+
+fn column(column: Pair<Rule>) -> Result<Column, TestScriptError> {
+    let mut parts = column.into_inner();
+    let var = parts.next().unwrap();
+    let name = var.as_str().to_string();
+    let variable = variable(var)?;
+
+    // the default format of the CPU emulator
+    let (format, left, width, right) = match parts.next() {
+        Some(format) => {
+            let mut parts = format.into_inner();
+            let format = match parts.next().unwrap().as_str() {
+                "B" => Format::Binary,
+                "X" => Format::Hex,
+                "D" => Format::Decimal,
+                "S" => Format::String,
+                _ => unreachable!(),
+            };
+            let left = count(parts.next().unwrap())?;
+            let width = count(parts.next().unwrap())?;
+            let right = count(parts.next().unwrap())?;
+            (format, left, width, right)
+        }
+        None => (Format::Binary, 1, 16, 1),
+    };
+
+    Ok(Column {
+        name,
+        variable,
+        format,
+        left,
+        width,
+        right,
+    })
+}
+
+fn condition(condition: Pair<Rule>) -> Result<Condition, TestScriptError> {
+    let mut parts = condition.into_inner();
+    let variable = variable(parts.next().unwrap())?;
+    let comparison = match parts.next().unwrap().as_str() {
+        "=" => Comparison::Equal,
+        "<>" => Comparison::NotEqual,
+        "<" => Comparison::Less,
+        "<=" => Comparison::LessOrEqual,
+        ">" => Comparison::Greater,
+        ">=" => Comparison::GreaterOrEqual,
+        _ => unreachable!(),
+    };
+    let value = value(parts.next().unwrap())?;
+
+    Ok(Condition {
+        variable,
+        comparison,
+        value,
+    })
+}
+
+fn text(pair: Pair<Rule>) -> String {
+    pair.as_str().to_string()
+}
+
+fn commands(commands: Pair<Rule>) -> Result<Vec<Command>, TestScriptError> {
+    commands.into_inner().map(command).collect()
+}
+
+fn command(command: Pair<Rule>) -> Result<Command, TestScriptError> {
+    let rule = command.as_rule();
+    let mut parts = command.into_inner();
+
+    Ok(match rule {
+        Rule::load => Command::Load(parts.next().map(text)),
+        Rule::output_file => Command::OutputFile(text(parts.next().unwrap())),
+        Rule::compare_to => Command::CompareTo(text(parts.next().unwrap())),
+        Rule::output_list => Command::OutputList(parts.map(column).collect::<Result<_, _>>()?),
+        Rule::output => Command::Output,
+        Rule::set => {
+            let variable = variable(parts.next().unwrap())?;
+            Command::Set(variable, value(parts.next().unwrap())?)
+        }
+        Rule::ticktock => Command::TickTock,
+        Rule::tick => Command::Tick,
+        Rule::tock => Command::Tock,
+        Rule::echo => Command::Echo(text(parts.next().unwrap()).trim_matches('"').to_string()),
+        Rule::clear_echo => Command::ClearEcho,
+        Rule::repeat => {
+            let count = count(parts.next().unwrap())?;
+            Command::Repeat(count, commands(parts.next().unwrap())?)
+        }
+        Rule::while_loop => {
+            let condition = condition(parts.next().unwrap())?;
+            Command::While(condition, commands(parts.next().unwrap())?)
+        }
+        _ => unreachable!(),
+    })
+}
+
+/// Parses a Nand2Tetris test script (.tst) for the CPU emulator.
+pub fn parse_test_script(input: &str) -> Result<Vec<Command>, TestScriptError> {
+    let mut script = TestScriptParser::parse(Rule::script, input)
+        .map_err(|e| TestScriptError::Syntax(e.to_string()))?;
+    let script = script.next().unwrap();
+
+    commands(script.into_inner().next().unwrap())
+}
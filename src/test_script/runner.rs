@@ -0,0 +1,325 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{assembler::Assembler, cpu::Cpu, rom::read_hack};
+
+use super::{
+    parse_test_script, Column, Command, Comparison, Condition, Format, TestScriptError, Variable,
+};
+
+/// What a test script produced once it ran to its end.
+pub struct TestReport {
+    /// The lines written by `output-list` and `output`, in the format of a .out file.
+    pub output: Vec<String>,
+    /// Messages of `echo` commands.
+    pub echoes: Vec<String>,
+    /// Whether the output was compared against a .cmp file.
+    pub compared: bool,
+}
+
+/// Executes test script commands on an emulated Hack CPU.
+/// File names inside of the script are resolved relative to `base_dir`.
+pub struct TestRunner {
+    base_dir: PathBuf,
+    cpu: Option<Cpu>,
+    time: usize,
+    output_list: Option<Vec<Column>>,
+    output_file: Option<PathBuf>,
+    compare_to: Option<Vec<String>>,
+    output: Vec<String>,
+    echoes: Vec<String>,
+}
+
+impl Column {
+    fn header(&self) -> String {
+        let total = self.left + self.width + self.right;
+        let name: String = self.name.chars().take(total).collect();
+        let left = (total - name.len()) / 2;
+
+        format!(
+            "{}{}{}",
+            " ".repeat(left),
+            name,
+            " ".repeat(total - left - name.len())
+        )
+    }
+
+    fn cell(&self, value: u16) -> String {
+        let text = match self.format {
+            Format::Binary => format!("{:016b}", value),
+            Format::Hex => format!("{:04X}", value),
+            Format::Decimal | Format::String => (value as i16).to_string(),
+        };
+
+        // binary and hex values keep their least significant digits if the column is too narrow
+        let text = match self.format {
+            Format::Binary | Format::Hex if text.len() > self.width => {
+                text[text.len() - self.width..].to_string()
+            }
+            _ => text,
+        };
+
+        let text = match self.format {
+            Format::String => format!("{:<width$}", text, width = self.width),
+            _ => format!("{:>width$}", text, width = self.width),
+        };
+
+        format!(
+            "{}{}{}",
+            " ".repeat(self.left),
+            text,
+            " ".repeat(self.right)
+        )
+    }
+}
+
+fn table_line(cells: impl Iterator<Item = String>) -> String {
+    let cells: Vec<_> = cells.collect();
+    format!("|{}|", cells.join("|"))
+}
+
+/// Compares a line of output against the compare file, `*` matches any character.
+fn lines_match(expected: &str, actual: &str) -> bool {
+    let expected = expected.trim_end();
+    let actual = actual.trim_end();
+
+    expected.len() == actual.len()
+        && expected
+            .chars()
+            .zip(actual.chars())
+            .all(|(e, a)| e == '*' || e == a)
+}
+
+impl TestRunner {
+    pub fn new(base_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            base_dir: base_dir.into(),
+            cpu: None,
+            time: 0,
+            output_list: None,
+            output_file: None,
+            compare_to: None,
+            output: Vec::new(),
+            echoes: Vec::new(),
+        }
+    }
+
+    fn read(&self, file_name: &str) -> Result<(PathBuf, String), TestScriptError> {
+        let path = self.base_dir.join(file_name);
+        match fs::read_to_string(&path) {
+            Ok(content) => Ok((path, content)),
+            Err(e) => Err(TestScriptError::Io(path, e)),
+        }
+    }
+
+    fn load(&mut self, file_name: &str) -> Result<(), TestScriptError> {
+        let (path, mut content) = self.read(file_name)?;
+
+        let program = if file_name.ends_with(".hack") {
+            read_hack(&content).map_err(|e| TestScriptError::Rom(path, e))?
+        } else {
+            // so programs dont have to end with a newline
+            content.push('\n');
+            Assembler::new(&content)
+                .with_file_name(&path.display().to_string())
+                .assemble()?
+        };
+
+        self.cpu = Some(Cpu::new(&program)?);
+        self.time = 0;
+        Ok(())
+    }
+
+    fn cpu(&mut self) -> Result<&mut Cpu, TestScriptError> {
+        self.cpu.as_mut().ok_or(TestScriptError::NoProgram)
+    }
+
+    fn get(&mut self, variable: Variable) -> Result<u16, TestScriptError> {
+        let time = self.time;
+        let cpu = self.cpu()?;
+
+        Ok(match variable {
+            Variable::A => cpu.a(),
+            Variable::D => cpu.d(),
+            Variable::Pc => cpu.pc(),
+            Variable::Ram(address) => cpu.ram()[address],
+            Variable::Time => time as u16,
+        })
+    }
+
+    fn set(&mut self, variable: Variable, value: i16) -> Result<(), TestScriptError> {
+        let value = value as u16;
+        let cpu = self.cpu()?;
+
+        match variable {
+            Variable::A => cpu.set_a(value),
+            Variable::D => cpu.set_d(value),
+            Variable::Pc => cpu.set_pc(value),
+            Variable::Ram(address) => cpu.ram_mut()[address] = value,
+            Variable::Time => self.time = value as usize,
+        }
+
+        Ok(())
+    }
+
+    fn is_true(&mut self, condition: &Condition) -> Result<bool, TestScriptError> {
+        let value = self.get(condition.variable)? as i16;
+
+        Ok(match condition.comparison {
+            Comparison::Equal => value == condition.value,
+            Comparison::NotEqual => value != condition.value,
+            Comparison::Less => value < condition.value,
+            Comparison::LessOrEqual => value <= condition.value,
+            Comparison::Greater => value > condition.value,
+            Comparison::GreaterOrEqual => value >= condition.value,
+        })
+    }
+
+    /// Appends a line to the output and compares it against the compare file.
+    fn write_line(&mut self, line: String) -> Result<(), TestScriptError> {
+        self.output.push(line);
+        let index = self.output.len() - 1;
+
+        if let Some(compare_to) = &self.compare_to {
+            let expected = compare_to
+                .get(index)
+                .map(String::as_str)
+                .unwrap_or_default();
+            let actual = &self.output[index];
+
+            if !lines_match(expected, actual) {
+                return Err(TestScriptError::ComparisonFailure {
+                    line: index + 1,
+                    expected: expected.to_string(),
+                    actual: actual.clone(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn output(&mut self) -> Result<(), TestScriptError> {
+        let columns = self
+            .output_list
+            .clone()
+            .ok_or(TestScriptError::NoOutputList)?;
+        let mut cells = Vec::new();
+        for column in &columns {
+            cells.push(column.cell(self.get(column.variable)?));
+        }
+
+        self.write_line(table_line(cells.into_iter()))
+    }
+
+    pub fn execute(&mut self, commands: &[Command]) -> Result<(), TestScriptError> {
+        for command in commands {
+            match command {
+                Command::Load(Some(file_name)) => self.load(file_name)?,
+                Command::Load(None) => return Err(TestScriptError::NoProgram),
+                Command::OutputFile(file_name) => {
+                    self.output_file = Some(self.base_dir.join(file_name))
+                }
+                Command::CompareTo(file_name) => {
+                    let (_, content) = self.read(file_name)?;
+                    self.compare_to = Some(content.lines().map(str::to_string).collect());
+                }
+                Command::OutputList(columns) => {
+                    let header = table_line(columns.iter().map(Column::header));
+                    self.output_list = Some(columns.clone());
+                    self.write_line(header)?;
+                }
+                Command::Output => self.output()?,
+                Command::Set(variable, value) => self.set(*variable, *value)?,
+                Command::TickTock | Command::Tock => {
+                    self.cpu()?.step();
+                    self.time += 1;
+                }
+                // the CPU only changes its state on the falling edge of the clock
+                Command::Tick => (),
+                Command::Echo(message) => self.echoes.push(message.clone()),
+                Command::ClearEcho => (),
+                Command::Repeat(count, commands) => {
+                    for _ in 0..*count {
+                        self.execute(commands)?;
+                    }
+                }
+                Command::While(condition, commands) => {
+                    while self.is_true(condition)? {
+                        self.execute(commands)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the output file, if the script requested one.
+    pub fn write_output_file(&self) -> Result<(), TestScriptError> {
+        if let Some(path) = &self.output_file {
+            let mut content = self.output.join("\n");
+            content.push('\n');
+            fs::write(path, content).map_err(|e| TestScriptError::Io(path.clone(), e))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn into_report(self) -> TestReport {
+        TestReport {
+            output: self.output,
+            echoes: self.echoes,
+            compared: self.compare_to.is_some(),
+        }
+    }
+}
+
+/// Runs a test script, writes its output file and compares it against its compare file.
+/// The output file is written even if the comparison fails.
+pub fn run_test_script(path: &Path) -> Result<TestReport, TestScriptError> {
+    let script = fs::read_to_string(path).map_err(|e| TestScriptError::Io(path.into(), e))?;
+    let commands = parse_test_script(&script)?;
+
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut runner = TestRunner::new(base_dir);
+    let result = runner.execute(&commands);
+    runner.write_output_file()?;
+    result?;
+
+    Ok(runner.into_report())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_format() {
+        let commands = parse_test_script(
+            "output-list RAM[0]%D2.6.2 A%B1.16.1 D%X1.4.1 PC;\nset A 5, set D -1, set RAM[0] -3;\noutput;",
+        )
+        .unwrap();
+
+        let program = Assembler::new("@0\n").assemble().unwrap();
+        let mut runner = TestRunner::new("");
+        runner.cpu = Some(Cpu::new(&program).unwrap());
+        runner.execute(&commands).unwrap();
+
+        assert_eq!(
+            runner.into_report().output,
+            [
+                "|  RAM[0]  |        A         |  D   |        PC        |",
+                "|      -3  | 0000000000000101 | FFFF | 0000000000000000 |",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_wildcard_comparison() {
+        assert!(lines_match("|  *  |   1 |", "|  7  |   1 |"));
+        assert!(!lines_match("|  *  |   1 |", "|  7  |   2 |"));
+    }
+}
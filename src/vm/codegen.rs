@@ -0,0 +1,331 @@
+use std::{fs, path::Path};
+
+use super::{parse_vm, ArithmeticCommand, Segment, VmCommand, VmError};
+
+/// Translates VM commands into Hack assembly.
+///
+/// Several files can be translated into one program, static variables are
+/// prefixed with the name of the file they belong to.
+///
+/// # Example
+/// ```
+/// # use hack_asm::vm::{parse_vm, VmTranslator};
+/// let commands = parse_vm("Add.vm", "push constant 7\npush constant 8\nadd\n").unwrap();
+/// let mut translator = VmTranslator::new();
+/// translator.translate("Add", &commands);
+///
+/// let asm = translator.into_output();
+/// assert!(asm.starts_with("// push constant 7\n@7\nD=A\n"));
+/// ```
+pub struct VmTranslator {
+    output: String,
+    file: String,
+    function: String,
+    counter: usize,
+}
+
+const PUSH_D: &str = "@SP\nA=M\nM=D\n@SP\nM=M+1\n";
+const POP_D: &str = "@SP\nAM=M-1\nD=M\n";
+
+impl VmTranslator {
+    pub fn new() -> Self {
+        Self {
+            output: String::new(),
+            file: String::new(),
+            function: String::new(),
+            counter: 0,
+        }
+    }
+
+    fn emit(&mut self, code: &str) {
+        self.output.push_str(code);
+    }
+
+    fn unique_label(&mut self, kind: &str) -> String {
+        self.counter += 1;
+        format!("{}${}.{}", self.function, kind, self.counter)
+    }
+
+    /// Sets the stack pointer to 256 and calls `Sys.init`.
+    pub fn bootstrap(&mut self) {
+        self.emit("// bootstrap\n@256\nD=A\n@SP\nM=D\n");
+        self.function = "Sys.bootstrap".to_string();
+        self.call("Sys.init", 0);
+    }
+
+    fn segment_base(segment: Segment) -> &'static str {
+        match segment {
+            Segment::Local => "LCL",
+            Segment::Argument => "ARG",
+            Segment::This => "THIS",
+            Segment::That => "THAT",
+            _ => unreachable!(),
+        }
+    }
+
+    /// The address of a segment that is not stored behind a pointer.
+    fn fixed_address(&self, segment: Segment, index: u16) -> String {
+        match segment {
+            Segment::Static => format!("{}.{}", self.file, index),
+            Segment::Pointer => (3 + index).to_string(),
+            Segment::Temp => (5 + index).to_string(),
+            _ => unreachable!(),
+        }
+    }
+
+    fn push(&mut self, segment: Segment, index: u16) {
+        let load = match segment {
+            Segment::Constant => format!("@{}\nD=A\n", index),
+            Segment::Static | Segment::Pointer | Segment::Temp => {
+                format!("@{}\nD=M\n", self.fixed_address(segment, index))
+            }
+            _ => format!(
+                "@{}\nD=A\n@{}\nA=D+M\nD=M\n",
+                index,
+                Self::segment_base(segment)
+            ),
+        };
+
+        self.emit(&load);
+        self.emit(PUSH_D);
+    }
+
+    fn pop(&mut self, segment: Segment, index: u16) {
+        match segment {
+            Segment::Static | Segment::Pointer | Segment::Temp => {
+                let address = self.fixed_address(segment, index);
+                self.emit(POP_D);
+                self.emit(&format!("@{}\nM=D\n", address));
+            }
+            Segment::Constant => unreachable!(),
+            _ => {
+                // the target address is kept in R13 while the stack is popped
+                self.emit(&format!(
+                    "@{}\nD=A\n@{}\nD=D+M\n@R13\nM=D\n",
+                    index,
+                    Self::segment_base(segment)
+                ));
+                self.emit(POP_D);
+                self.emit("@R13\nA=M\nM=D\n");
+            }
+        }
+    }
+
+    fn arithmetic(&mut self, command: ArithmeticCommand) {
+        let binary = |operation: &str| format!("{}A=A-1\n{}\n", POP_D, operation);
+
+        let code = match command {
+            ArithmeticCommand::Add => binary("M=D+M"),
+            ArithmeticCommand::Sub => binary("M=M-D"),
+            ArithmeticCommand::And => binary("M=D&M"),
+            ArithmeticCommand::Or => binary("M=D|M"),
+            ArithmeticCommand::Neg => "@SP\nA=M-1\nM=-M\n".to_string(),
+            ArithmeticCommand::Not => "@SP\nA=M-1\nM=!M\n".to_string(),
+            ArithmeticCommand::Eq | ArithmeticCommand::Gt | ArithmeticCommand::Lt => {
+                let jump = match command {
+                    ArithmeticCommand::Eq => "JEQ",
+                    ArithmeticCommand::Gt => "JGT",
+                    _ => "JLT",
+                };
+                let label = self.unique_label("cmp");
+
+                // assume true and overwrite the result with false if the jump is not taken
+                format!(
+                    "{}A=A-1\nD=M-D\nM=-1\n@{label}\nD;{}\n@SP\nA=M-1\nM=0\n({label})\n",
+                    POP_D,
+                    jump,
+                    label = label
+                )
+            }
+        };
+
+        self.emit(&code);
+    }
+
+    fn call(&mut self, function: &str, arguments: u16) {
+        let return_address = self.unique_label("ret");
+
+        self.emit(&format!("@{}\nD=A\n{}", return_address, PUSH_D));
+        for pointer in ["LCL", "ARG", "THIS", "THAT"] {
+            self.emit(&format!("@{}\nD=M\n{}", pointer, PUSH_D));
+        }
+        self.emit(&format!(
+            "@SP\nD=M\n@{}\nD=D-A\n@ARG\nM=D\n@SP\nD=M\n@LCL\nM=D\n@{}\n0;JMP\n({})\n",
+            5 + arguments as usize,
+            function,
+            return_address
+        ));
+    }
+
+    fn function(&mut self, name: &str, locals: u16) {
+        self.function = name.to_string();
+        self.emit(&format!("({})\n", name));
+
+        for _ in 0..locals {
+            self.emit("@SP\nA=M\nM=0\n@SP\nM=M+1\n");
+        }
+    }
+
+    fn return_from_function(&mut self) {
+        // R13 holds the frame, R14 the return address
+        self.emit("@LCL\nD=M\n@R13\nM=D\n@5\nA=D-A\nD=M\n@R14\nM=D\n");
+        self.emit(POP_D);
+        self.emit("@ARG\nA=M\nM=D\n@ARG\nD=M+1\n@SP\nM=D\n");
+        for pointer in ["THAT", "THIS", "ARG", "LCL"] {
+            self.emit(&format!("@R13\nAM=M-1\nD=M\n@{}\nM=D\n", pointer));
+        }
+        self.emit("@R14\nA=M\n0;JMP\n");
+    }
+
+    fn command_text(command: &VmCommand) -> String {
+        match command {
+            VmCommand::Push(segment, index) => {
+                format!("push {} {}", format!("{:?}", segment).to_lowercase(), index)
+            }
+            VmCommand::Pop(segment, index) => {
+                format!("pop {} {}", format!("{:?}", segment).to_lowercase(), index)
+            }
+            VmCommand::Arithmetic(command) => format!("{:?}", command).to_lowercase(),
+            VmCommand::Label(label) => format!("label {}", label),
+            VmCommand::Goto(label) => format!("goto {}", label),
+            VmCommand::IfGoto(label) => format!("if-goto {}", label),
+            VmCommand::Function(name, locals) => format!("function {} {}", name, locals),
+            VmCommand::Call(name, arguments) => format!("call {} {}", name, arguments),
+            VmCommand::Return => "return".to_string(),
+        }
+    }
+
+    /// Translates the commands of a single file.
+    /// `file` is the name of the file without its extension, it prefixes static variables.
+    pub fn translate(&mut self, file: &str, commands: &[VmCommand]) {
+        self.file = file.to_string();
+
+        for command in commands {
+            self.emit(&format!("// {}\n", Self::command_text(command)));
+
+            match command {
+                VmCommand::Push(segment, index) => self.push(*segment, *index),
+                VmCommand::Pop(segment, index) => self.pop(*segment, *index),
+                VmCommand::Arithmetic(command) => self.arithmetic(*command),
+                VmCommand::Label(label) => {
+                    let code = format!("({}${})\n", self.function, label);
+                    self.emit(&code);
+                }
+                VmCommand::Goto(label) => {
+                    let code = format!("@{}${}\n0;JMP\n", self.function, label);
+                    self.emit(&code);
+                }
+                VmCommand::IfGoto(label) => {
+                    let code = format!("{}@{}${}\nD;JNE\n", POP_D, self.function, label);
+                    self.emit(&code);
+                }
+                VmCommand::Function(name, locals) => self.function(name, *locals),
+                VmCommand::Call(name, arguments) => self.call(name, *arguments),
+                VmCommand::Return => self.return_from_function(),
+            }
+        }
+    }
+
+    pub fn into_output(self) -> String {
+        self.output
+    }
+}
+
+impl Default for VmTranslator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Translates a single .vm file or every .vm file inside of a directory.
+/// If one of the files is `Sys.vm` the program starts with the bootstrap code calling `Sys.init`.
+pub fn translate_path(path: &Path) -> Result<String, VmError> {
+    let mut files = Vec::new();
+
+    if path.is_dir() {
+        let entries = fs::read_dir(path).map_err(|e| VmError::Io(path.into(), e))?;
+        for entry in entries {
+            let entry = entry.map_err(|e| VmError::Io(path.into(), e))?;
+            if entry.path().extension().is_some_and(|e| e == "vm") {
+                files.push(entry.path());
+            }
+        }
+        files.sort();
+    } else {
+        files.push(path.to_path_buf());
+    }
+
+    if files.is_empty() {
+        return Err(VmError::NoInput(path.into()));
+    }
+
+    let mut translator = VmTranslator::new();
+    if files
+        .iter()
+        .any(|f| f.file_stem().is_some_and(|s| s == "Sys"))
+    {
+        translator.bootstrap();
+    }
+
+    for file in files {
+        let input = fs::read_to_string(&file).map_err(|e| VmError::Io(file.clone(), e))?;
+        let commands = parse_vm(&file.display().to_string(), &input)?;
+        let name = file.file_stem().unwrap_or_default().to_string_lossy();
+
+        translator.translate(&name, &commands);
+    }
+
+    Ok(translator.into_output())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Assembler, Cpu};
+
+    fn run(files: &[(&str, &str)], bootstrap: bool) -> Cpu {
+        let mut translator = VmTranslator::new();
+        if bootstrap {
+            translator.bootstrap();
+        }
+        for (name, source) in files {
+            translator.translate(name, &parse_vm(name, source).unwrap());
+        }
+
+        let asm = translator.into_output();
+        let program = Assembler::new(&asm).assemble().unwrap();
+        let mut cpu = Cpu::new(&program).unwrap();
+        cpu.ram_mut()[0] = 256;
+        cpu.run(10_000);
+        cpu
+    }
+
+    #[test]
+    fn test_stack_arithmetic() {
+        let cpu = run(
+            &[(
+                "Stack",
+                "push constant 17\npush constant 17\neq\npush constant 892\npush constant 891\nlt\npush constant 57\npush constant 31\nsub\nneg\n",
+            )],
+            false,
+        );
+
+        assert_eq!(cpu.ram()[0], 259);
+        assert_eq!(&cpu.ram()[256..259], &[0xFFFF, 0, (-26i16) as u16]);
+    }
+
+    #[test]
+    fn test_function_calls() {
+        let sys =
+            "function Sys.init 0\npush constant 4\ncall Main.fibonacci 1\nlabel END\ngoto END\n";
+        let main =
+            "function Main.fibonacci 0\npush argument 0\npush constant 2\nlt\nif-goto BASE\n\
+                    push argument 0\npush constant 2\nsub\ncall Main.fibonacci 1\n\
+                    push argument 0\npush constant 1\nsub\ncall Main.fibonacci 1\nadd\nreturn\n\
+                    label BASE\npush argument 0\nreturn\n";
+        let cpu = run(&[("Main", main), ("Sys", sys)], true);
+
+        // fibonacci(4) is left on top of the stack of Sys.init
+        assert_eq!(cpu.ram()[cpu.ram()[0] as usize - 1], 3);
+    }
+}
@@ -0,0 +1,63 @@
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+mod codegen;
+mod parser;
+
+pub use codegen::{translate_path, VmTranslator};
+pub use parser::parse_vm;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Segment {
+    Argument,
+    Local,
+    Static,
+    Constant,
+    This,
+    That,
+    Pointer,
+    Temp,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArithmeticCommand {
+    Add,
+    Sub,
+    Neg,
+    Eq,
+    Gt,
+    Lt,
+    And,
+    Or,
+    Not,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VmCommand {
+    Push(Segment, u16),
+    Pop(Segment, u16),
+    Arithmetic(ArithmeticCommand),
+    Label(String),
+    Goto(String),
+    IfGoto(String),
+    Function(String, u16),
+    Call(String, u16),
+    Return,
+}
+
+#[derive(Error, Debug)]
+pub enum VmError {
+    #[error("{0}")]
+    Syntax(String),
+    #[error("{file}:{line}: {message}")]
+    InvalidCommand {
+        file: String,
+        line: usize,
+        message: String,
+    },
+    #[error("error reading {}: {}", .0.display(), .1)]
+    Io(PathBuf, std::io::Error),
+    #[error("no .vm files found in {}", .0.display())]
+    NoInput(PathBuf),
+}
@@ -0,0 +1,173 @@
+use pest::{iterators::Pair, Parser};
+
+use super::{ArithmeticCommand, Segment, VmCommand, VmError};
+
+#[derive(Parser)]
+#[grammar = "grammar/vm.pest"]
+pub struct VmParser;
+
+fn segment(segment: Pair<Rule>) -> Segment {
+    match segment.as_str() {
+        "argument" => Segment::Argument,
+        "local" => Segment::Local,
+        "static" => Segment::Static,
+        "constant" => Segment::Constant,
+        "this" => Segment::This,
+        "that" => Segment::That,
+        "pointer" => Segment::Pointer,
+        "temp" => Segment::Temp,
+        _ => unreachable!(),
+    }
+}
+
+fn arithmetic(arithmetic: Pair<Rule>) -> ArithmeticCommand {
+    match arithmetic.as_str() {
+        "add" => ArithmeticCommand::Add,
+        "sub" => ArithmeticCommand::Sub,
+        "neg" => ArithmeticCommand::Neg,
+        "eq" => ArithmeticCommand::Eq,
+        "gt" => ArithmeticCommand::Gt,
+        "lt" => ArithmeticCommand::Lt,
+        "and" => ArithmeticCommand::And,
+        "or" => ArithmeticCommand::Or,
+        "not" => ArithmeticCommand::Not,
+        _ => unreachable!(),
+    }
+}
+
+/// Checks that the index is inside of the segment.
+fn memory_access(file: &str, command: Pair<Rule>) -> Result<(Segment, u16), VmError> {
+    let line = command.as_span().start_pos().line_col().0;
+    let is_pop = command.as_rule() == Rule::pop;
+    let mut parts = command.into_inner();
+    let segment = segment(parts.next().unwrap());
+    let index = parts.next().unwrap().as_str();
+
+    let limit = match segment {
+        Segment::Constant => crate::hack_int::HackInt::MAX as usize + 1,
+        Segment::Pointer => 2,
+        Segment::Temp => 8,
+        _ => u16::MAX as usize,
+    };
+
+    let error = |message: String| VmError::InvalidCommand {
+        file: file.to_string(),
+        line,
+        message,
+    };
+
+    let index = match index.parse::<usize>() {
+        Ok(index) if index < limit => index as u16,
+        _ => {
+            return Err(error(format!(
+                "index {} is out of bounds for segment {:?}",
+                index, segment
+            )))
+        }
+    };
+
+    if is_pop && segment == Segment::Constant {
+        return Err(error("cannot pop into the constant segment".to_string()));
+    }
+
+    Ok((segment, index))
+}
+
+fn argument_count(count: Pair<Rule>) -> Result<u16, VmError> {
+    count
+        .as_str()
+        .parse()
+        .map_err(|_| VmError::Syntax(format!("{} is not a valid count", count.as_str())))
+}
+
+/// Parses the commands of a single .vm file, `file` is only used for error messages.
+pub fn parse_vm(file: &str, input: &str) -> Result<Vec<VmCommand>, VmError> {
+    let program = VmParser::parse(Rule::program, input)
+        .map_err(|e| VmError::Syntax(e.with_path(file).to_string()))?;
+    let mut commands = Vec::new();
+
+    for command in program {
+        let rule = command.as_rule();
+        let command = match rule {
+            Rule::push => {
+                let (segment, index) = memory_access(file, command)?;
+                VmCommand::Push(segment, index)
+            }
+            Rule::pop => {
+                let (segment, index) = memory_access(file, command)?;
+                VmCommand::Pop(segment, index)
+            }
+            Rule::arithmetic => VmCommand::Arithmetic(arithmetic(command)),
+            Rule::label | Rule::goto | Rule::if_goto => {
+                let name = command.into_inner().next().unwrap().as_str().to_string();
+                match rule {
+                    Rule::label => VmCommand::Label(name),
+                    Rule::goto => VmCommand::Goto(name),
+                    _ => VmCommand::IfGoto(name),
+                }
+            }
+            Rule::function | Rule::call => {
+                let mut parts = command.into_inner();
+                let name = parts.next().unwrap().as_str().to_string();
+                let count = argument_count(parts.next().unwrap())?;
+                match rule {
+                    Rule::function => VmCommand::Function(name, count),
+                    _ => VmCommand::Call(name, count),
+                }
+            }
+            Rule::return_command => VmCommand::Return,
+            Rule::EOI => continue,
+            _ => unreachable!(),
+        };
+
+        commands.push(command);
+    }
+
+    Ok(commands)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commands() {
+        let commands = parse_vm(
+            "Test.vm",
+            "push constant 7\npop local 0\ncall Main.f 2\nreturn\n",
+        );
+        assert_eq!(
+            commands.unwrap(),
+            [
+                VmCommand::Push(Segment::Constant, 7),
+                VmCommand::Pop(Segment::Local, 0),
+                VmCommand::Call("Main.f".to_string(), 2),
+                VmCommand::Return,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_keywords_end_with_the_word() {
+        for input in [
+            "pushconstant7",
+            "push constant7",
+            "push constant 7x",
+            "popx",
+            "pop localx 0",
+            "labelEND",
+            "gotoEND",
+            "if-gotoEND",
+            "functionMain.f 0",
+            "callMain.f 0",
+            "returnx",
+            "addx",
+        ] {
+            assert!(
+                matches!(parse_vm("Test.vm", input), Err(VmError::Syntax(_))),
+                "{}",
+                input
+            );
+        }
+    }
+}